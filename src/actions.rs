@@ -1,47 +1,96 @@
 use std::f32::consts::PI;
 
-use bevy::{math::vec2, prelude::*, window::PrimaryWindow};
+use bevy::{math::vec2, prelude::*, utils::HashSet, window::PrimaryWindow};
 
 use crate::{
-    items, sprites, tiles, ui, DroppedItem, GameWorld, InputState, ItemMover, ItemProcessor,
-    PlacedTile, Player, ResourceProducer, ResourceTile, TileRotation,
+    cursor_cell, cursor_world_pos,
+    history::{BuildAction, BuildActionKind, BuildHistory},
+    item_cell, items,
+    placement::Placement,
+    rails::{RailNetwork, TrainStop},
+    robots::{LogisticChest, LogisticChestKind, Roboport, CHEST_CAPACITY},
+    sprites,
+    stats::ProductionStats,
+    tiles,
+    turrets::{GunTurret, TURRET_CAPACITY},
+    ui, DroppedItem, GameWorld, GhostTile, Health, InputState, ItemContainer, ItemMover,
+    ItemProcessor, ItemType, PlacedTile, Player, ResourceProducer, ResourceTile, ResourceType,
+    TileRotation, TileSettings, TileType,
 };
 
+const PLAYER_BUILD_INTERVAL: f32 = 0.25;
+
+#[derive(Resource)]
+pub struct PlayerConstruction {
+    timer: Timer,
+}
+
+impl Default for PlayerConstruction {
+    fn default() -> Self {
+        Self {
+            timer: Timer::from_seconds(PLAYER_BUILD_INTERVAL, TimerMode::Repeating),
+        }
+    }
+}
+
+/// How far from the player tiles can be built and removed, and items dropped and picked up.
+#[derive(Component)]
+pub struct PlayerReach {
+    pub build: f32,
+    pub mining: f32,
+    pub pickup: f32,
+}
+
+impl Default for PlayerReach {
+    fn default() -> Self {
+        Self {
+            build: 10.0 * 32.0,
+            mining: 6.0 * 32.0,
+            pickup: 2.0 * 32.0,
+        }
+    }
+}
+
+/// Resource cell that is being mined by hand.
+pub struct HandMining {
+    cell: (i32, i32),
+    duration: f32,
+    elapsed: f32,
+}
+
 pub fn handle_player_actions(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     q_windows: Query<&Window, With<PrimaryWindow>>,
     q_camera: Query<(&Camera, &GlobalTransform)>,
-    mut q_player: Query<&mut Player>,
+    mut q_player: Query<(&mut Player, &Transform, &PlayerReach)>,
     mut input_state: ResMut<InputState>,
     mut game_world: ResMut<GameWorld>,
     q_items: Query<(Entity, &Transform, &DroppedItem)>,
     q_tiles: Query<(Entity, &PlacedTile)>,
     q_resource_tiles: Query<(Entity, &ResourceTile)>,
+    mut rail_network: ResMut<RailNetwork>,
+    mut history: ResMut<BuildHistory>,
+    q_chests: Query<&LogisticChest>,
+    q_contents: ContentsQuery,
+    placement: Placement,
     time: Res<Time>,
 ) {
-    let mut player = q_player.single_mut();
+    let (mut player, player_transform, reach) = q_player.single_mut();
+    let player_pos = player_transform.translation.truncate();
+    let in_reach = |target: Vec2, range: f32| target.distance(player_pos) <= range;
 
     if input_state.toggling_inventory_visible {
         if let Some(e) = input_state.inventory_ui {
             commands.entity(e).despawn_recursive();
             input_state.inventory_ui = None;
         } else {
-            input_state.inventory_ui = Some(ui::create_player_inventory_ui(
-                commands,
-                &asset_server,
-                player.inventory.as_ref(),
-            ));
+            input_state.inventory_ui = Some(ui::create_player_inventory_ui(commands));
         }
         return;
     }
-    let window = q_windows.single();
     let (camera, camera_transform) = q_camera.single();
-
-    let mouse_pos = window
-        .cursor_position()
-        .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor))
-        .map(|ray| ray.origin.truncate());
+    let mouse_pos = cursor_world_pos(q_windows.single(), camera, camera_transform);
 
     if let Some(pos) = mouse_pos {
         if input_state.dropping_items {
@@ -59,7 +108,7 @@ pub fn handle_player_actions(
                 }
             }
 
-            if perform_action {
+            if perform_action && in_reach(pos, reach.build) {
                 if let Some(item_type) = input_state.item_in_hand {
                     if player.decrement_inventory(item_type) {
                         let xx = (pos.x + 8.0) / 32.0;
@@ -97,8 +146,8 @@ pub fn handle_player_actions(
 
             if perform_action {
                 if let Some((e, _, it)) = q_items.iter().find(|(_, tr, _)| {
-                    (tr.translation - vec2(8.0, -8.0).extend(0.0)).distance_squared(pos.extend(0.0))
-                        < 16.0 * 16.0
+                    let item_pos = (tr.translation - vec2(8.0, -8.0).extend(0.0)).truncate();
+                    item_pos.distance_squared(pos) < 16.0 * 16.0 && in_reach(item_pos, reach.pickup)
                 }) {
                     if player.increment_inventory(it.item_type) {
                         commands.entity(e).despawn_recursive();
@@ -112,38 +161,30 @@ pub fn handle_player_actions(
         if input_state.deleting_tile {
             match input_state.deleting_tile_timer.as_mut() {
                 Some(timer) => {
-                    if timer.tick(time.delta()).finished() {
+                    if timer.tick(time.delta()).finished() && in_reach(pos, reach.mining) {
                         let xx = (pos.x / 32.0 + 0.5).floor() as i32;
                         let yy = (pos.y / 32.0 + 0.5).floor() as i32;
-                        let mut deleted = false;
-                        for (entity, tile) in q_tiles
-                            .iter()
-                            .filter(|(_, tile)| tile.x == xx && tile.y == yy)
-                        {
-                            deleted = true;
-                            if game_world.tiles.remove(&(xx, yy)).is_some() {
-                                // TODO: check return value when we don't need a loop to avoid multiple items (for inserters)
-                                player.increment_inventory(tile.tile_type.item_to_drop);
-                            }
-                            commands.entity(entity).despawn();
+                        // trains on top of the rails are removed first
+                        let train_on_tile = rail_network.is_occupied(xx, yy);
+                        if let Some(ghost) = game_world.ghosts.remove(&(xx, yy)) {
+                            commands.entity(ghost).despawn();
                         }
-                        if !deleted {
-                            if let Some((_, res)) = q_resource_tiles
-                                .iter()
-                                .find(|(_, t)| t.x == xx && t.y == yy)
-                            {
-                                let item = DroppedItem {
-                                    item_type: res.resource_type.item_to_produce,
-                                };
-                                commands.spawn((
-                                    sprites::create_dropped_item_sprite(
-                                        &asset_server,
-                                        &item,
-                                        (pos.x + 8.0) / 32.0,
-                                        (pos.y - 8.0) / 32.0,
-                                    ),
-                                    item,
-                                ));
+                        if !train_on_tile {
+                            let settings = tile_settings(&q_tiles, &q_chests, (xx, yy));
+                            if let Some(removed) = deconstruct_tile(
+                                &mut commands,
+                                &asset_server,
+                                &mut player,
+                                &mut game_world,
+                                &q_tiles,
+                                &q_contents,
+                                (xx, yy),
+                            ) {
+                                history.record(BuildAction {
+                                    kind: BuildActionKind::Deleted,
+                                    tile: removed,
+                                    settings,
+                                });
                             }
                         }
                     }
@@ -157,7 +198,11 @@ pub fn handle_player_actions(
             input_state.deleting_tile_timer = None;
         }
 
-        if let Some(drag_start) = input_state.drag_start {
+        // dragging with a selection tool selects an area instead
+        let drag_start = input_state
+            .drag_start
+            .filter(|_| input_state.selection_tool.is_none());
+        if let Some(drag_start) = drag_start {
             let prev_x = drag_start.x;
             let prev_y = drag_start.y;
             let curr_x = pos.x;
@@ -176,118 +221,627 @@ pub fn handle_player_actions(
                 let xx = (x / 32.0 + 0.5).floor() as i32;
                 let yy = (y / 32.0 + 0.5).floor() as i32;
 
-                if let Some(item_in_hand) = input_state.item_in_hand {
-                    if !game_world.tiles.contains_key(&(xx, yy)) {
-                        match item_in_hand {
-                            i if i == items::BELT => {
-                                let tile = PlacedTile {
-                                    tile_type: tiles::BELT,
-                                    rotation: input_state.rotation,
-                                    x: xx,
-                                    y: yy,
-                                };
-                                if player.decrement_inventory(tile.tile_type.item_to_drop) {
-                                    game_world.tiles.insert((xx, yy), tile.clone());
-
-                                    commands.spawn((
-                                        sprites::create_tile_sprite(&asset_server, &tile),
-                                        tile,
-                                    ));
-                                }
-                            }
-                            i if i == items::MINER => {
-                                let tile = PlacedTile {
-                                    tile_type: tiles::MINER,
-                                    rotation: input_state.rotation,
-                                    x: xx,
-                                    y: yy,
-                                };
-
-                                if player.decrement_inventory(tile.tile_type.item_to_drop) {
-                                    game_world.tiles.insert((xx, yy), tile.clone());
-
-                                    if let Some((_, res)) = q_resource_tiles
-                                        .iter()
-                                        .find(|(_, t)| t.x == xx && t.y == yy)
-                                    {
-                                        commands.spawn((
-                                            sprites::create_tile_sprite(&asset_server, &tile),
-                                            tile,
-                                            ResourceProducer {
-                                                timer: Timer::from_seconds(1.0, TimerMode::Once),
-                                                resource: res.resource_type,
-                                            },
-                                        ));
-                                    } else {
-                                        commands.spawn((
-                                            sprites::create_tile_sprite(&asset_server, &tile),
-                                            tile,
-                                        ));
-                                    }
-                                }
-                            }
-                            i if i == items::INSERTER => {
-                                let tile = PlacedTile {
-                                    tile_type: tiles::INSERTER,
-                                    rotation: input_state.rotation,
-                                    x: xx,
-                                    y: yy,
-                                };
-
-                                if player.decrement_inventory(tile.tile_type.item_to_drop) {
-                                    game_world.tiles.insert((xx, yy), tile.clone());
-
-                                    let anchor = vec2(0.0, -0.5 + 3.0 / 32.0);
-
-                                    let main_sprite =
-                                        sprites::create_tile_sprite(&asset_server, &tile);
-                                    let rotating_sprite = sprites::create_rotating_tile_sprite(
-                                        &asset_server,
-                                        &tile,
-                                        anchor,
-                                        PI * 0.5,
-                                    );
-
-                                    commands.spawn((tile.clone(), main_sprite));
-                                    commands.spawn((
-                                        tile,
-                                        rotating_sprite,
-                                        TileRotation {
-                                            anchor,
-                                            speed: 2.0,
-                                            from: -PI * 0.5,
-                                            to: PI * 0.5,
-                                            time: 1.0,
-                                        },
-                                        ItemMover { item: None },
-                                    ));
-                                }
-                            }
-                            i if i == items::FURNACE => {
-                                let tile = PlacedTile {
-                                    tile_type: tiles::FURNACE,
-                                    rotation: input_state.rotation,
-                                    x: xx,
-                                    y: yy,
-                                };
-
-                                if player.decrement_inventory(tile.tile_type.item_to_drop) {
-                                    game_world.tiles.insert((xx, yy), tile.clone());
-
-                                    commands.spawn((
-                                        sprites::create_tile_sprite(&asset_server, &tile),
-                                        ItemProcessor {
-                                            timer: Timer::from_seconds(3.0, TimerMode::Once),
-                                            item: None,
-                                            output: None,
-                                        },
-                                        tile,
-                                    ));
-                                }
-                            }
-                            _ => {}
-                        };
+                let Some(tile_type) = input_state.item_in_hand.and_then(tiles::for_item) else {
+                    continue;
+                };
+                if !placement.is_valid(&game_world, tile_type, xx, yy)
+                    || !in_reach(vec2(xx as f32, yy as f32) * 32.0, reach.build)
+                {
+                    continue;
+                }
+                let tile = PlacedTile {
+                    tile_type,
+                    rotation: input_state.rotation,
+                    x: xx,
+                    y: yy,
+                };
+
+                // without the item a ghost is placed, to be built later
+                if input_state.placing_ghosts || !player.decrement_inventory(tile_type.item_to_drop)
+                {
+                    spawn_ghost(&mut commands, &asset_server, &mut game_world, tile, None);
+                } else {
+                    if let Some(ghost) = game_world.ghosts.remove(&(xx, yy)) {
+                        commands.entity(ghost).despawn();
                     }
+                    game_world.tiles.insert((xx, yy), tile.clone());
+                    history.record(BuildAction {
+                        kind: BuildActionKind::Placed,
+                        tile: tile.clone(),
+                        settings: None,
+                    });
+
+                    let resource = q_resource_tiles
+                        .iter()
+                        .find(|(_, t)| t.x == xx && t.y == yy)
+                        .map(|(_, t)| t.resource_type);
+                    spawn_tile(
+                        &mut commands,
+                        &asset_server,
+                        &mut rail_network,
+                        tile,
+                        resource,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Spawns the entities of a tile that has just been added to `GameWorld.tiles`.
+/// Returns the entity holding the `Health` of the tile.
+pub fn spawn_tile(
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    rail_network: &mut RailNetwork,
+    tile: PlacedTile,
+    resource: Option<ResourceType>,
+) -> Entity {
+    let health = Health::new(tile.tile_type.max_health as f32);
+    match tile.tile_type {
+        t if t == tiles::MINER => {
+            if let Some(resource) = resource {
+                commands
+                    .spawn((
+                        sprites::create_tile_sprite(asset_server, &tile),
+                        tile,
+                        health,
+                        ResourceProducer {
+                            timer: Timer::from_seconds(1.0, TimerMode::Once),
+                            resource,
+                        },
+                    ))
+                    .id()
+            } else {
+                commands
+                    .spawn((
+                        sprites::create_tile_sprite(asset_server, &tile),
+                        tile,
+                        health,
+                    ))
+                    .id()
+            }
+        }
+        t if t == tiles::INSERTER || t == tiles::FAST_INSERTER => {
+            let speed = inserter_speed(t);
+            let anchor = vec2(0.0, -0.5 + 3.0 / 32.0);
+
+            let main_sprite = sprites::create_tile_sprite(asset_server, &tile);
+            let rotating_sprite =
+                sprites::create_rotating_tile_sprite(asset_server, &tile, anchor, PI * 0.5);
+
+            let main = commands.spawn((tile.clone(), main_sprite, health)).id();
+            commands.spawn((
+                tile,
+                rotating_sprite,
+                TileRotation {
+                    anchor,
+                    speed,
+                    from: -PI * 0.5,
+                    to: PI * 0.5,
+                    time: 1.0,
+                },
+                ItemMover { item: None },
+            ));
+            main
+        }
+        t if t == tiles::FURNACE => commands
+            .spawn((
+                sprites::create_tile_sprite(asset_server, &tile),
+                ItemProcessor {
+                    timer: Timer::from_seconds(3.0, TimerMode::Once),
+                    item: None,
+                    output: None,
+                },
+                tile,
+                health,
+            ))
+            .id(),
+        t if t == tiles::TRAIN_STOP => {
+            let name = rail_network.next_station_name();
+            let rotation = tile.rotation;
+            commands
+                .spawn((
+                    sprites::create_tile_sprite(asset_server, &tile),
+                    tile,
+                    health,
+                    TrainStop { name: name.clone() },
+                ))
+                .with_children(|parent| {
+                    parent.spawn(sprites::create_label_sprite(name, rotation));
+                })
+                .id()
+        }
+        t if t == tiles::ROBOPORT => commands
+            .spawn((
+                sprites::create_tile_sprite(asset_server, &tile),
+                tile,
+                health,
+                Roboport::default(),
+            ))
+            .id(),
+        t if t == tiles::PROVIDER_CHEST
+            || t == tiles::REQUESTER_CHEST
+            || t == tiles::STORAGE_CHEST =>
+        {
+            let kind = if t == tiles::PROVIDER_CHEST {
+                LogisticChestKind::Provider
+            } else if t == tiles::REQUESTER_CHEST {
+                LogisticChestKind::Requester
+            } else {
+                LogisticChestKind::Storage
+            };
+            commands
+                .spawn((
+                    sprites::create_tile_sprite(asset_server, &tile),
+                    tile,
+                    health,
+                    LogisticChest::new(kind),
+                    ItemContainer::new(CHEST_CAPACITY),
+                ))
+                .id()
+        }
+        t if t == tiles::GUN_TURRET => commands
+            .spawn((
+                sprites::create_tile_sprite(asset_server, &tile),
+                tile,
+                health,
+                GunTurret::default(),
+                ItemContainer {
+                    filter: Some(items::AMMO),
+                    extractable: false,
+                    ..ItemContainer::new(TURRET_CAPACITY)
+                },
+            ))
+            .id(),
+        _ => commands
+            .spawn((
+                sprites::create_tile_sprite(asset_server, &tile),
+                tile,
+                health,
+            ))
+            .id(),
+    }
+}
+
+pub fn inserter_speed(tile_type: TileType) -> f32 {
+    if tile_type == tiles::FAST_INSERTER {
+        4.0
+    } else {
+        2.0
+    }
+}
+
+/// Places a ghost of the tile unless the cell already has one.
+pub fn spawn_ghost(
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    game_world: &mut GameWorld,
+    tile: PlacedTile,
+    settings: Option<TileSettings>,
+) {
+    if game_world.ghosts.contains_key(&(tile.x, tile.y)) {
+        return;
+    }
+    let cell = (tile.x, tile.y);
+    let ghost = commands
+        .spawn((
+            sprites::create_ghost_sprite(asset_server, &tile),
+            GhostTile {
+                tile,
+                claimed: false,
+                settings,
+            },
+        ))
+        .id();
+    game_world.ghosts.insert(cell, ghost);
+}
+
+/// Applies copied settings to a tile entity returned by `spawn_tile`.
+pub fn apply_tile_settings(commands: &mut Commands, entity: Entity, settings: TileSettings) {
+    match settings {
+        TileSettings::Request(item_type) => {
+            let mut chest = LogisticChest::new(LogisticChestKind::Requester);
+            chest.request = Some(item_type);
+            commands.entity(entity).insert(chest);
+        }
+    }
+}
+
+/// The player builds the nearest ghost in range for which they carry the item, one at a time.
+pub fn build_nearby_ghosts(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut q_player: Query<(&mut Player, &Transform, &PlayerReach)>,
+    q_ghosts: Query<(Entity, &GhostTile)>,
+    mut game_world: ResMut<GameWorld>,
+    mut rail_network: ResMut<RailNetwork>,
+    q_resource_tiles: Query<&ResourceTile>,
+    placement: Placement,
+    mut construction: ResMut<PlayerConstruction>,
+    time: Res<Time>,
+) {
+    if !construction.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let (mut player, transform, reach) = q_player.single_mut();
+    let pos = transform.translation.truncate();
+    let distance = |tile: &PlacedTile| (vec2(tile.x as f32, tile.y as f32) * 32.0).distance(pos);
+
+    let Some((ghost_entity, ghost)) = q_ghosts
+        .iter()
+        .filter(|(_, g)| {
+            !g.claimed
+                && distance(&g.tile) <= reach.build
+                && player.has_item_in_inventory(g.tile.tile_type.item_to_drop)
+                && placement.is_valid(&game_world, g.tile.tile_type, g.tile.x, g.tile.y)
+        })
+        .min_by(|(_, a), (_, b)| distance(&a.tile).total_cmp(&distance(&b.tile)))
+    else {
+        return;
+    };
+
+    let tile = ghost.tile.clone();
+    player.decrement_inventory(tile.tile_type.item_to_drop);
+    game_world.ghosts.remove(&(tile.x, tile.y));
+    commands.entity(ghost_entity).despawn();
+    game_world.tiles.insert((tile.x, tile.y), tile.clone());
+
+    let resource = q_resource_tiles
+        .iter()
+        .find(|t| t.x == tile.x && t.y == tile.y)
+        .map(|t| t.resource_type);
+    let entity = spawn_tile(
+        &mut commands,
+        &asset_server,
+        &mut rail_network,
+        tile,
+        resource,
+    );
+    if let Some(settings) = ghost.settings {
+        apply_tile_settings(&mut commands, entity, settings);
+    }
+}
+
+pub type ContentsQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Option<&'static ItemProcessor>,
+        Option<&'static ItemMover>,
+        Option<&'static ItemContainer>,
+        Option<&'static Roboport>,
+    ),
+>;
+
+/// Items held by a tile entity, e.g. a furnace's input and output or an inserter's hand.
+pub fn tile_contents(q_contents: &ContentsQuery, entity: Entity) -> Vec<(ItemType, usize)> {
+    let Ok((processor, mover, container, roboport)) = q_contents.get(entity) else {
+        return Vec::new();
+    };
+    let mut contents = Vec::new();
+    if let Some(processor) = processor {
+        contents.extend(processor.item.map(|item| (item, 1)));
+        contents.extend(processor.output);
+    }
+    if let Some(item) = mover.and_then(|mover| mover.item) {
+        contents.push((item, 1));
+    }
+    if let Some(container) = container {
+        contents.extend(container.items.iter().copied());
+    }
+    if let Some(roboport) = roboport {
+        contents.extend(roboport.robot_items());
+    }
+    contents
+}
+
+/// Removes the tile at the cell and gives its item and contents to the player.
+pub fn deconstruct_tile(
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    player: &mut Player,
+    game_world: &mut GameWorld,
+    q_tiles: &Query<(Entity, &PlacedTile)>,
+    q_contents: &ContentsQuery,
+    (x, y): (i32, i32),
+) -> Option<PlacedTile> {
+    let tile = game_world.tiles.remove(&(x, y))?;
+    give_or_drop(
+        commands,
+        asset_server,
+        player,
+        tile.tile_type.item_to_drop,
+        x,
+        y,
+    );
+    for (entity, _) in q_tiles.iter().filter(|(_, t)| t.x == x && t.y == y) {
+        for (item_type, count) in tile_contents(q_contents, entity) {
+            for _ in 0..count {
+                give_or_drop(commands, asset_server, player, item_type, x, y);
+            }
+        }
+        commands.entity(entity).despawn_recursive();
+    }
+    Some(tile)
+}
+
+/// Settings of the tile at the given cell that are kept when it is removed and placed again.
+pub fn tile_settings(
+    q_tiles: &Query<(Entity, &PlacedTile)>,
+    q_chests: &Query<&LogisticChest>,
+    (x, y): (i32, i32),
+) -> Option<TileSettings> {
+    q_tiles
+        .iter()
+        .filter(|(_, t)| t.x == x && t.y == y)
+        .find_map(|(e, _)| q_chests.get(e).ok())
+        .and_then(|chest| chest.request)
+        .map(TileSettings::Request)
+}
+
+/// Resources with nothing built on them are mined by hand while the right mouse button is held.
+pub fn mine_by_hand(
+    q_windows: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<(&Camera, &GlobalTransform)>,
+    mut q_player: Query<(&mut Player, &Transform, &PlayerReach)>,
+    mut input_state: ResMut<InputState>,
+    game_world: Res<GameWorld>,
+    q_resource_tiles: Query<&ResourceTile>,
+    mut stats: ResMut<ProductionStats>,
+    time: Res<Time>,
+) {
+    let (mut player, player_transform, reach) = q_player.single_mut();
+    let (camera, camera_transform) = q_camera.single();
+    let resource = cursor_cell(q_windows.single(), camera, camera_transform)
+        .filter(|&(x, y)| {
+            let distance =
+                (vec2(x as f32, y as f32) * 32.0).distance(player_transform.translation.truncate());
+            input_state.deleting_tile
+                && distance <= reach.mining
+                && !game_world.tiles.contains_key(&(x, y))
+                && !game_world.ghosts.contains_key(&(x, y))
+        })
+        .and_then(|(x, y)| q_resource_tiles.iter().find(|t| t.x == x && t.y == y));
+    let Some(resource) = resource else {
+        input_state.mining = None;
+        return;
+    };
+
+    let cell = (resource.x, resource.y);
+    let resource = resource.resource_type;
+    let mut mining = match input_state.mining.take() {
+        Some(mining) if mining.cell == cell => mining,
+        _ => HandMining {
+            cell,
+            duration: resource.mining_time_ms as f32 / 1000.0,
+            elapsed: 0.0,
+        },
+    };
+    mining.elapsed += time.delta_seconds();
+    // with a full inventory mining stops until there is room again, as dropping
+    // the item on the resource would keep miners from being built there
+    if mining.elapsed >= mining.duration {
+        mining.elapsed = if player.increment_inventory(resource.item_to_produce) {
+            stats.record_produced(resource.item_to_produce, 1);
+            0.0
+        } else {
+            mining.duration
+        };
+    }
+    input_state.mining = Some(mining);
+}
+
+/// Draws the progress of hand mining above the mined cell.
+pub fn draw_mining_progress(mut gizmos: Gizmos, input_state: Res<InputState>) {
+    let Some(mining) = input_state.mining.as_ref() else {
+        return;
+    };
+    let (x, y) = mining.cell;
+    let start = vec2(x as f32, y as f32) * 32.0 + vec2(-12.0, 18.0);
+    let fraction = (mining.elapsed / mining.duration).clamp(0.0, 1.0);
+    gizmos.line_2d(start, start + vec2(24.0, 0.0), Color::srgb(0.2, 0.2, 0.2));
+    gizmos.line_2d(
+        start,
+        start + vec2(24.0 * fraction, 0.0),
+        Color::srgb(0.9, 0.7, 0.1),
+    );
+}
+
+/// How far around the cell they were dropped on spilled items are spread.
+const SPILL_RADIUS: i32 = 6;
+
+/// An item that didn't fit into the inventory, moved to a free cell by `spread_spilled_items`.
+#[derive(Component)]
+pub struct SpilledItem;
+
+/// Puts an item into the player inventory, or drops it near the given cell if the inventory is full.
+pub fn give_or_drop(
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    player: &mut Player,
+    item_type: ItemType,
+    x: i32,
+    y: i32,
+) {
+    if player.increment_inventory(item_type) {
+        return;
+    }
+    let item = DroppedItem { item_type };
+    commands.spawn((
+        sprites::create_dropped_item_sprite(asset_server, &item, x as f32 + 0.25, y as f32 - 0.25),
+        item,
+        SpilledItem,
+    ));
+}
+
+/// Moves spilled items to the nearest cell without a tile, a ghost, a resource or another item,
+/// so emptying a full chest doesn't pile everything up on one spot or block miners.
+pub fn spread_spilled_items(
+    mut commands: Commands,
+    game_world: Res<GameWorld>,
+    mut q_spilled: Query<(Entity, &mut Transform), With<SpilledItem>>,
+    q_items: Query<&Transform, (With<DroppedItem>, Without<SpilledItem>)>,
+    q_resource_tiles: Query<&ResourceTile>,
+) {
+    if q_spilled.is_empty() {
+        return;
+    }
+    let mut occupied: HashSet<(i32, i32)> = q_items.iter().map(item_cell).collect();
+    // items on a resource would block building miners there
+    occupied.extend(q_resource_tiles.iter().map(|t| (t.x, t.y)));
+
+    for (entity, mut transform) in q_spilled.iter_mut() {
+        commands.entity(entity).remove::<SpilledItem>();
+        let (x, y) = item_cell(&transform);
+        let free = (0..=SPILL_RADIUS)
+            .flat_map(|r| {
+                (-r..=r)
+                    .flat_map(move |dx| (-r..=r).map(move |dy| (dx, dy)))
+                    .filter(move |(dx, dy)| dx.abs().max(dy.abs()) == r)
+            })
+            .map(|(dx, dy)| (x + dx, y + dy))
+            .find(|cell| {
+                !occupied.contains(cell)
+                    && !game_world.tiles.contains_key(cell)
+                    && !game_world.ghosts.contains_key(cell)
+            });
+        // with no free cell nearby the item stays where it is
+        let cell = free.unwrap_or((x, y));
+        occupied.insert(cell);
+        transform.translation.x = cell.0 as f32 * 32.0 + 8.0;
+        transform.translation.y = cell.1 as f32 * 32.0 - 8.0;
+    }
+}
+
+#[derive(Event)]
+pub struct TileRotated {
+    pub x: i32,
+    pub y: i32,
+}
+
+/// Changes the rotation of a placed tile in `GameWorld`, its entities follow in `apply_tile_rotations`.
+pub fn rotate_tile(
+    game_world: &mut GameWorld,
+    rail_network: &RailNetwork,
+    tile_rotated: &mut EventWriter<TileRotated>,
+    x: i32,
+    y: i32,
+    rotation: u8,
+) -> bool {
+    // rails can't turn below a train
+    if rail_network.is_occupied(x, y) {
+        return false;
+    }
+    let Some(tile) = game_world.tiles.get_mut(&(x, y)) else {
+        return false;
+    };
+    tile.rotation = rotation;
+    tile_rotated.send(TileRotated { x, y });
+    true
+}
+
+/// Q picks the item and rotation of the hovered tile into hand, or empties the hand.
+pub fn handle_pipette(
+    q_windows: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<(&Camera, &GlobalTransform)>,
+    mut input_state: ResMut<InputState>,
+    game_world: Res<GameWorld>,
+    q_player: Query<&Player>,
+) {
+    if !input_state.clearing_hand {
+        return;
+    }
+    input_state.selection_tool = None;
+
+    let (camera, camera_transform) = q_camera.single();
+    let hovered = cursor_cell(q_windows.single(), camera, camera_transform)
+        .and_then(|cell| game_world.tiles.get(&cell));
+
+    // the hand is only filled with items the player carries, otherwise it is emptied
+    match hovered {
+        Some(tile)
+            if q_player
+                .single()
+                .has_item_in_inventory(tile.tile_type.item_to_drop) =>
+        {
+            input_state.item_in_hand = Some(tile.tile_type.item_to_drop);
+            input_state.rotation = tile.rotation;
+        }
+        _ => input_state.item_in_hand = None,
+    }
+}
+
+/// R rotates the hovered tile, or the item in hand when no tile is hovered.
+pub fn handle_rotation(
+    q_windows: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<(&Camera, &GlobalTransform)>,
+    mut input_state: ResMut<InputState>,
+    mut game_world: ResMut<GameWorld>,
+    rail_network: Res<RailNetwork>,
+    mut history: ResMut<BuildHistory>,
+    mut tile_rotated: EventWriter<TileRotated>,
+) {
+    if !input_state.rotating {
+        return;
+    }
+    let (camera, camera_transform) = q_camera.single();
+    let hovered = cursor_cell(q_windows.single(), camera, camera_transform)
+        .and_then(|cell| game_world.tiles.get(&cell).cloned());
+
+    let Some(tile) = hovered else {
+        input_state.rotation += 3;
+        input_state.rotation %= 4;
+        return;
+    };
+    let rotation = (tile.rotation + 3) % 4;
+    if rotate_tile(
+        &mut game_world,
+        &rail_network,
+        &mut tile_rotated,
+        tile.x,
+        tile.y,
+        rotation,
+    ) {
+        history.record(BuildAction {
+            kind: BuildActionKind::Rotated {
+                from: tile.rotation,
+            },
+            tile: PlacedTile { rotation, ..tile },
+            settings: None,
+        });
+    }
+}
+
+pub fn apply_tile_rotations(
+    mut tile_rotated: EventReader<TileRotated>,
+    game_world: Res<GameWorld>,
+    mut q_tiles: Query<(
+        &mut PlacedTile,
+        &mut Transform,
+        Option<&mut TileRotation>,
+        Option<&Children>,
+    )>,
+    mut q_labels: Query<&mut Transform, (With<Text>, Without<PlacedTile>)>,
+) {
+    for event in tile_rotated.read() {
+        let Some(tile) = game_world.tiles.get(&(event.x, event.y)) else {
+            continue;
+        };
+        for (mut placed, mut transform, rotating_part, children) in q_tiles
+            .iter_mut()
+            .filter(|(t, ..)| t.x == event.x && t.y == event.y)
+        {
+            placed.rotation = tile.rotation;
+            match rotating_part {
+                // the hand finishes its swing, `update_rotating_tiles` places it
+                Some(mut rotating_part) => rotating_part.time = 1.0,
+                None => transform.rotation = Quat::from_rotation_z(PI / 2.0 * tile.rotation as f32),
+            }
+            // labels stay upright
+            for &child in children.into_iter().flatten() {
+                if let Ok(mut label) = q_labels.get_mut(child) {
+                    label.rotation = Quat::from_rotation_z(-PI / 2.0 * tile.rotation as f32);
                 }
             }
         }
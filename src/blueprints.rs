@@ -0,0 +1,389 @@
+use bevy::{math::vec2, prelude::*, window::PrimaryWindow};
+
+use crate::{
+    actions::{self, PlayerReach},
+    cursor_cell,
+    history::{BuildAction, BuildActionKind, BuildHistory},
+    items,
+    placement::Placement,
+    rails::RailNetwork,
+    robots::LogisticChest,
+    selection::AreaSelected,
+    tiles, GameWorld, InputState, PlacedTile, Player, ResourceTile, SelectionTool, TileSettings,
+    TileType,
+};
+
+/// Versioned prefix of exported blueprint strings.
+const TEXT_PREFIX: &str = "bp1;";
+#[cfg(not(target_arch = "wasm32"))]
+const EXPORT_FILE: &str = "blueprint.txt";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlueprintEntry {
+    dx: i32,
+    dy: i32,
+    tile_type: TileType,
+    rotation: u8,
+    settings: Option<TileSettings>,
+}
+
+impl BlueprintEntry {
+    /// Returns the entry turned by the given number of quarter turns counterclockwise.
+    fn rotated(&self, rotation: u8) -> Self {
+        let (mut dx, mut dy) = (self.dx, self.dy);
+        for _ in 0..rotation {
+            (dx, dy) = (-dy, dx);
+        }
+        Self {
+            dx,
+            dy,
+            rotation: (self.rotation + rotation) % 4,
+            ..self.clone()
+        }
+    }
+}
+
+/// Tiles stored relative to the center of the area they were copied from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Blueprint {
+    entries: Vec<BlueprintEntry>,
+}
+
+impl Blueprint {
+    /// Encodes the blueprint as `bp1;` followed by `dx,dy,tile,rotation[,request]` entries separated by `;`.
+    pub fn to_text(&self) -> String {
+        let entries: Vec<String> = self
+            .entries
+            .iter()
+            .map(|e| {
+                let mut text = format!(
+                    "{},{},{},{}",
+                    e.dx, e.dy, e.tile_type.texture_name, e.rotation
+                );
+                if let Some(TileSettings::Request(item_type)) = e.settings {
+                    text.push(',');
+                    text.push_str(item_type.texture_name);
+                }
+                text
+            })
+            .collect();
+        format!("{}{}", TEXT_PREFIX, entries.join(";"))
+    }
+
+    pub fn from_text(text: &str) -> Option<Blueprint> {
+        let entries = text
+            .trim()
+            .strip_prefix(TEXT_PREFIX)?
+            .split(';')
+            .filter(|e| !e.is_empty())
+            .map(|e| {
+                let fields: Vec<&str> = e.split(',').collect();
+                if fields.len() < 4 || fields.len() > 5 {
+                    return None;
+                }
+                let tile_type = tiles::by_name(fields[2])?;
+                // only requester chests have settings
+                let settings = match fields.get(4) {
+                    Some(name) if tile_type == tiles::REQUESTER_CHEST => {
+                        Some(TileSettings::Request(items::by_name(name)?))
+                    }
+                    Some(_) => return None,
+                    None => None,
+                };
+                Some(BlueprintEntry {
+                    dx: fields[0].parse().ok()?,
+                    dy: fields[1].parse().ok()?,
+                    tile_type,
+                    rotation: fields[3].parse::<u8>().ok().filter(|r| *r < 4)?,
+                    settings,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Blueprint { entries })
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct BlueprintState {
+    in_hand: Option<Blueprint>,
+    /// Pasting happens once per click, not every frame the button is held.
+    mouse_was_down: bool,
+}
+
+impl BlueprintState {
+    pub fn has_blueprint(&self) -> bool {
+        self.in_hand.is_some()
+    }
+}
+
+fn capture_blueprint(
+    game_world: &GameWorld,
+    q_chests: &Query<(&PlacedTile, &LogisticChest)>,
+    area: &AreaSelected,
+) -> Blueprint {
+    let (cx, cy) = ((area.min.0 + area.max.0) / 2, (area.min.1 + area.max.1) / 2);
+
+    let mut entries: Vec<BlueprintEntry> = game_world
+        .tiles
+        .values()
+        .filter(|t| area.contains(t.x, t.y))
+        .map(|t| BlueprintEntry {
+            dx: t.x - cx,
+            dy: t.y - cy,
+            tile_type: t.tile_type,
+            rotation: t.rotation,
+            settings: q_chests
+                .iter()
+                .find(|(c, _)| c.x == t.x && c.y == t.y)
+                .and_then(|(_, chest)| chest.request)
+                .map(TileSettings::Request),
+        })
+        .collect();
+    entries.sort_by_key(|e| (e.dy, e.dx));
+    Blueprint { entries }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod browser {
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen]
+    extern "C" {
+        /// `window.prompt`, which returns `None` when the dialog is cancelled.
+        pub fn prompt(message: &str, default: &str) -> Option<String>;
+    }
+}
+
+fn export_blueprint(blueprint: &Blueprint) {
+    let text = blueprint.to_text();
+    info!("blueprint: {}", text);
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Err(err) = std::fs::write(EXPORT_FILE, &text) {
+        warn!("could not write {}: {}", EXPORT_FILE, err);
+    }
+    // the text field of the dialog lets the player copy the string
+    #[cfg(target_arch = "wasm32")]
+    browser::prompt("Copy the blueprint:", &text);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn import_blueprint() -> Option<Blueprint> {
+    let text = std::fs::read_to_string(EXPORT_FILE)
+        .map_err(|err| warn!("could not read {}: {}", EXPORT_FILE, err))
+        .ok()?;
+    let blueprint = Blueprint::from_text(&text);
+    if blueprint.is_none() {
+        warn!("{} does not contain a valid blueprint", EXPORT_FILE);
+    }
+    blueprint
+}
+
+#[cfg(target_arch = "wasm32")]
+fn import_blueprint() -> Option<Blueprint> {
+    let text = browser::prompt("Paste a blueprint:", "")?;
+    let blueprint = Blueprint::from_text(&text);
+    if blueprint.is_none() {
+        warn!("the pasted text is not a valid blueprint");
+    }
+    blueprint
+}
+
+pub fn handle_blueprint_actions(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    q_windows: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<(&Camera, &GlobalTransform)>,
+    mut q_player: Query<(&mut Player, &Transform, &PlayerReach)>,
+    mut input_state: ResMut<InputState>,
+    mut state: ResMut<BlueprintState>,
+    mut game_world: ResMut<GameWorld>,
+    mut rail_network: ResMut<RailNetwork>,
+    q_chests: Query<(&PlacedTile, &LogisticChest)>,
+    q_resource_tiles: Query<&ResourceTile>,
+    placement: Placement,
+    mut history: ResMut<BuildHistory>,
+    mut area_selected: EventReader<AreaSelected>,
+    mut gizmos: Gizmos,
+) {
+    if input_state.toggling_blueprint_tool {
+        if input_state.selection_tool == Some(SelectionTool::Blueprint) || state.in_hand.is_some() {
+            input_state.selection_tool = None;
+            state.in_hand = None;
+        } else {
+            input_state.selection_tool = Some(SelectionTool::Blueprint);
+            input_state.item_in_hand = None;
+        }
+    }
+    // selecting an item puts the blueprint away
+    if input_state.clearing_hand || input_state.item_in_hand.is_some() {
+        state.in_hand = None;
+    }
+    if input_state.importing_blueprint {
+        if let Some(blueprint) = import_blueprint() {
+            input_state.item_in_hand = None;
+            input_state.selection_tool = None;
+            state.in_hand = Some(blueprint);
+        }
+    }
+
+    let (camera, camera_transform) = q_camera.single();
+    let Some((xx, yy)) = cursor_cell(q_windows.single(), camera, camera_transform) else {
+        return;
+    };
+    let mouse_down = input_state.drag_start.is_some();
+    let clicked = mouse_down && !state.mouse_was_down;
+    state.mouse_was_down = mouse_down;
+
+    for area in area_selected.read() {
+        if area.tool != SelectionTool::Blueprint {
+            continue;
+        }
+        let blueprint = capture_blueprint(&game_world, &q_chests, area);
+        if !blueprint.entries.is_empty() {
+            input_state.selection_tool = None;
+            state.in_hand = Some(blueprint);
+        }
+    }
+    if input_state.selection_tool.is_some() {
+        return;
+    }
+
+    let Some(blueprint) = state.in_hand.as_ref() else {
+        return;
+    };
+    if input_state.exporting_blueprint {
+        export_blueprint(blueprint);
+    }
+    let entries: Vec<BlueprintEntry> = blueprint
+        .entries
+        .iter()
+        .map(|e| e.rotated(input_state.rotation))
+        .collect();
+
+    for e in &entries {
+        let cell = vec2((xx + e.dx) as f32, (yy + e.dy) as f32) * 32.0;
+        let color = if placement.is_valid(&game_world, e.tile_type, xx + e.dx, yy + e.dy) {
+            Color::srgb(0.3, 0.6, 1.0)
+        } else {
+            Color::srgb(0.9, 0.3, 0.2)
+        };
+        gizmos.rect_2d(cell, 0.0, Vec2::splat(28.0), color);
+    }
+
+    if !clicked {
+        return;
+    }
+    let (mut player, player_transform, reach) = q_player.single_mut();
+    let player_pos = player_transform.translation.truncate();
+    for e in entries {
+        let (x, y) = (xx + e.dx, yy + e.dy);
+        if !placement.is_valid(&game_world, e.tile_type, x, y) {
+            continue;
+        }
+        let in_reach = (vec2(x as f32, y as f32) * 32.0).distance(player_pos) <= reach.build;
+        let tile = PlacedTile {
+            tile_type: e.tile_type,
+            rotation: e.rotation,
+            x,
+            y,
+        };
+
+        if in_reach && player.decrement_inventory(e.tile_type.item_to_drop) {
+            if let Some(ghost) = game_world.ghosts.remove(&(x, y)) {
+                commands.entity(ghost).despawn();
+            }
+            game_world.tiles.insert((x, y), tile.clone());
+            history.record(BuildAction {
+                kind: BuildActionKind::Placed,
+                tile: tile.clone(),
+                settings: e.settings,
+            });
+
+            let resource = q_resource_tiles
+                .iter()
+                .find(|t| t.x == x && t.y == y)
+                .map(|t| t.resource_type);
+            let entity = actions::spawn_tile(
+                &mut commands,
+                &asset_server,
+                &mut rail_network,
+                tile,
+                resource,
+            );
+            if let Some(settings) = e.settings {
+                actions::apply_tile_settings(&mut commands, entity, settings);
+            }
+        } else {
+            // tiles out of reach or with missing items are left to construction robots,
+            // or built by the player when walking by
+            actions::spawn_ghost(
+                &mut commands,
+                &asset_server,
+                &mut game_world,
+                tile,
+                e.settings,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Blueprint {
+        Blueprint {
+            entries: vec![
+                BlueprintEntry {
+                    dx: -1,
+                    dy: 0,
+                    tile_type: tiles::BELT,
+                    rotation: 1,
+                    settings: None,
+                },
+                BlueprintEntry {
+                    dx: 2,
+                    dy: -3,
+                    tile_type: tiles::REQUESTER_CHEST,
+                    rotation: 0,
+                    settings: Some(TileSettings::Request(items::IRON_SHEET)),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let blueprint = sample();
+        let text = blueprint.to_text();
+        assert!(text.starts_with(TEXT_PREFIX));
+        assert_eq!(Blueprint::from_text(&text), Some(blueprint));
+    }
+
+    #[test]
+    fn rejects_malformed_text() {
+        for text in [
+            "",
+            "1,0,belt,0",
+            "bp2;1,0,belt,0",
+            "bp1;1,0,belt",
+            "bp1;1,0,belt,0,iron_sheet,1",
+            "bp1;x,0,belt,0",
+            "bp1;1,0,no_such_tile,0",
+            "bp1;1,0,belt,4",
+            "bp1;1,0,requester_chest,0,no_such_item",
+            "bp1;0,0,belt,0,iron_sheet",
+        ] {
+            assert_eq!(Blueprint::from_text(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn four_rotations_return_the_original() {
+        for entry in sample().entries {
+            let turned = (0..4).fold(entry.clone(), |e, _| e.rotated(1));
+            assert_eq!(turned, entry);
+            assert_eq!(entry.rotated(4), entry);
+        }
+    }
+}
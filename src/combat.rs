@@ -0,0 +1,152 @@
+use bevy::{prelude::*, sprite::MaterialMesh2dBundle, window::PrimaryWindow};
+
+use crate::{
+    cursor_world_pos, enemies::Enemy, health::DamageEvent, items, turrets::SHOTS_PER_AMMO,
+    InputState, Layer, Player,
+};
+
+const PROJECTILE_SPEED: f32 = 600.0;
+const PROJECTILE_LIFETIME: f32 = 0.5;
+const PROJECTILE_DAMAGE: f32 = 8.0;
+const HIT_RADIUS: f32 = 10.0;
+const FIRE_INTERVAL: f32 = 0.25;
+
+/// Cooldown and remaining shots of the weapon in the player's weapon slot.
+#[derive(Resource)]
+pub struct WeaponState {
+    cooldown: Timer,
+    shots_left: u32,
+}
+
+impl Default for WeaponState {
+    fn default() -> Self {
+        Self {
+            cooldown: Timer::from_seconds(FIRE_INTERVAL, TimerMode::Once),
+            shots_left: 0,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Projectile {
+    velocity: Vec2,
+    lifetime: Timer,
+}
+
+#[derive(Resource)]
+pub struct ProjectileAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<ColorMaterial>,
+}
+
+pub fn setup_projectile_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.insert_resource(ProjectileAssets {
+        mesh: meshes.add(Circle::new(2.0)),
+        material: materials.add(Color::srgb(1.0, 0.9, 0.4)),
+    });
+}
+
+/// Clicking with a weapon in hand moves it to the weapon slot, holding space fires it towards the mouse.
+pub fn handle_shooting(
+    mut commands: Commands,
+    q_windows: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<(&Camera, &GlobalTransform)>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    mut q_player: Query<(&mut Player, &Transform)>,
+    mut input_state: ResMut<InputState>,
+    mut weapon_state: ResMut<WeaponState>,
+    projectile_assets: Res<ProjectileAssets>,
+    time: Res<Time>,
+) {
+    let (mut player, player_transform) = q_player.single_mut();
+
+    let equipping = input_state.item_in_hand == Some(items::PISTOL)
+        && input_state.drag_start.is_some()
+        && buttons.just_pressed(MouseButton::Left)
+        && player.weapon != Some(items::PISTOL);
+    if equipping && player.decrement_inventory(items::PISTOL) {
+        if let Some(previous) = player.weapon.replace(items::PISTOL) {
+            // the old weapon stays equipped if there's no room for it, and the pistol
+            // goes back into the stack it was just taken from
+            if !player.increment_inventory(previous) {
+                player.weapon = Some(previous);
+                let _ = player.increment_inventory(items::PISTOL);
+            }
+        }
+        if !player.has_item_in_inventory(items::PISTOL) {
+            input_state.item_in_hand = None;
+        }
+    }
+
+    weapon_state.cooldown.tick(time.delta());
+    if !input_state.shooting || player.weapon.is_none() || !weapon_state.cooldown.finished() {
+        return;
+    }
+
+    let (camera, camera_transform) = q_camera.single();
+    let Some(target) = cursor_world_pos(q_windows.single(), camera, camera_transform) else {
+        return;
+    };
+    let pos = player_transform.translation.truncate();
+    let Some(dir) = (target - pos).try_normalize() else {
+        return;
+    };
+
+    if weapon_state.shots_left == 0 {
+        if !player.decrement_inventory(items::AMMO) {
+            return;
+        }
+        weapon_state.shots_left = SHOTS_PER_AMMO;
+    }
+    weapon_state.shots_left -= 1;
+    weapon_state.cooldown.reset();
+
+    commands.spawn((
+        Projectile {
+            velocity: dir * PROJECTILE_SPEED,
+            lifetime: Timer::from_seconds(PROJECTILE_LIFETIME, TimerMode::Once),
+        },
+        MaterialMesh2dBundle {
+            mesh: projectile_assets.mesh.clone().into(),
+            material: projectile_assets.material.clone(),
+            transform: Transform::from_translation(pos.extend(Layer::Projectile.depth())),
+            ..default()
+        },
+    ));
+}
+
+pub fn update_projectiles(
+    mut commands: Commands,
+    mut q_projectiles: Query<(Entity, &mut Projectile, &mut Transform), Without<Enemy>>,
+    q_enemies: Query<(Entity, &Transform), With<Enemy>>,
+    mut damage_events: EventWriter<DamageEvent>,
+    time: Res<Time>,
+) {
+    for (entity, mut projectile, mut transform) in q_projectiles.iter_mut() {
+        let start = transform.translation.truncate();
+        let end = start + projectile.velocity * time.delta_seconds();
+        transform.translation = end.extend(Layer::Projectile.depth());
+
+        // fast projectiles are checked along the whole segment they moved this step
+        let hit = q_enemies.iter().find(|(_, tr)| {
+            let p = tr.translation.truncate();
+            let segment = end - start;
+            let t = ((p - start).dot(segment) / segment.length_squared()).clamp(0.0, 1.0);
+            p.distance(start + segment * t) < HIT_RADIUS
+        });
+
+        if let Some((enemy, _)) = hit {
+            damage_events.send(DamageEvent {
+                target: enemy,
+                amount: PROJECTILE_DAMAGE,
+            });
+            commands.entity(entity).despawn();
+        } else if projectile.lifetime.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,636 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::{actions, items, stats::ProductionStats, ui, ItemType, Player};
+
+/// How deep missing intermediates are crafted, e.g. gears for belts for fast belts.
+const MAX_INTERMEDIATE_DEPTH: usize = 4;
+
+const COLOR_QUEUE_BG: Color = Color::hsv(0.0, 0.0, 0.3);
+const COLOR_PROGRESS: Color = Color::srgb(0.9, 0.7, 0.1);
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum RecipeCategory {
+    #[default]
+    Logistics,
+    Production,
+    Intermediate,
+    Combat,
+}
+
+impl RecipeCategory {
+    pub const ALL: [RecipeCategory; 4] = [
+        RecipeCategory::Logistics,
+        RecipeCategory::Production,
+        RecipeCategory::Intermediate,
+        RecipeCategory::Combat,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RecipeCategory::Logistics => "Logistics",
+            RecipeCategory::Production => "Production",
+            RecipeCategory::Intermediate => "Intermediate",
+            RecipeCategory::Combat => "Combat",
+        }
+    }
+}
+
+pub struct Recipe {
+    pub result: ItemType,
+    pub category: RecipeCategory,
+    /// Position in the crafting grid of the category, lower comes first.
+    pub order: u32,
+    pub ingredients: &'static [(ItemType, usize)],
+    /// Time it takes to craft the result by hand.
+    pub time_ms: u32,
+}
+
+impl Recipe {
+    fn time(&self) -> f32 {
+        self.time_ms as f32 / 1000.0
+    }
+}
+
+static RECIPES: [Recipe; 23] = [
+    Recipe {
+        result: items::IRON_GEAR,
+        category: RecipeCategory::Intermediate,
+        order: 1,
+        ingredients: &[(items::IRON_SHEET, 2)],
+        time_ms: 500,
+    },
+    Recipe {
+        result: items::BELT,
+        category: RecipeCategory::Logistics,
+        order: 1,
+        ingredients: &[(items::IRON_SHEET, 1), (items::IRON_GEAR, 1)],
+        time_ms: 500,
+    },
+    Recipe {
+        result: items::INSERTER,
+        category: RecipeCategory::Logistics,
+        order: 3,
+        ingredients: &[
+            (items::IRON_SHEET, 1),
+            (items::COPPER_SHEET, 1),
+            (items::IRON_GEAR, 1),
+        ],
+        time_ms: 500,
+    },
+    Recipe {
+        result: items::FURNACE,
+        category: RecipeCategory::Production,
+        order: 2,
+        ingredients: &[(items::IRON_SHEET, 1)],
+        time_ms: 1000,
+    },
+    Recipe {
+        result: items::MINER,
+        category: RecipeCategory::Production,
+        order: 1,
+        ingredients: &[(items::IRON_SHEET, 1), (items::IRON_GEAR, 2)],
+        time_ms: 2000,
+    },
+    Recipe {
+        result: items::FAST_BELT,
+        category: RecipeCategory::Logistics,
+        order: 2,
+        ingredients: &[(items::BELT, 1), (items::IRON_GEAR, 2)],
+        time_ms: 500,
+    },
+    Recipe {
+        result: items::FAST_INSERTER,
+        category: RecipeCategory::Logistics,
+        order: 4,
+        ingredients: &[
+            (items::INSERTER, 1),
+            (items::IRON_SHEET, 1),
+            (items::COPPER_SHEET, 1),
+        ],
+        time_ms: 500,
+    },
+    Recipe {
+        result: items::RAIL,
+        category: RecipeCategory::Logistics,
+        order: 11,
+        ingredients: &[(items::IRON_SHEET, 1)],
+        time_ms: 500,
+    },
+    Recipe {
+        result: items::CURVED_RAIL,
+        category: RecipeCategory::Logistics,
+        order: 12,
+        ingredients: &[(items::IRON_SHEET, 1)],
+        time_ms: 500,
+    },
+    Recipe {
+        result: items::TRAIN_STOP,
+        category: RecipeCategory::Logistics,
+        order: 13,
+        ingredients: &[(items::IRON_SHEET, 1), (items::COPPER_SHEET, 1)],
+        time_ms: 500,
+    },
+    Recipe {
+        result: items::LOCOMOTIVE,
+        category: RecipeCategory::Logistics,
+        order: 14,
+        ingredients: &[(items::IRON_SHEET, 1), (items::COPPER_SHEET, 1)],
+        time_ms: 4000,
+    },
+    Recipe {
+        result: items::CARGO_WAGON,
+        category: RecipeCategory::Logistics,
+        order: 15,
+        ingredients: &[(items::IRON_SHEET, 1)],
+        time_ms: 1000,
+    },
+    Recipe {
+        result: items::ROBOPORT,
+        category: RecipeCategory::Logistics,
+        order: 21,
+        ingredients: &[(items::IRON_SHEET, 1), (items::COPPER_SHEET, 1)],
+        time_ms: 5000,
+    },
+    Recipe {
+        result: items::LOGISTIC_ROBOT,
+        category: RecipeCategory::Logistics,
+        order: 22,
+        ingredients: &[(items::IRON_SHEET, 1), (items::COPPER_SHEET, 1)],
+        time_ms: 500,
+    },
+    Recipe {
+        result: items::CONSTRUCTION_ROBOT,
+        category: RecipeCategory::Logistics,
+        order: 23,
+        ingredients: &[(items::IRON_SHEET, 1), (items::COPPER_SHEET, 1)],
+        time_ms: 500,
+    },
+    Recipe {
+        result: items::PROVIDER_CHEST,
+        category: RecipeCategory::Logistics,
+        order: 5,
+        ingredients: &[(items::IRON_SHEET, 1)],
+        time_ms: 500,
+    },
+    Recipe {
+        result: items::REQUESTER_CHEST,
+        category: RecipeCategory::Logistics,
+        order: 6,
+        ingredients: &[(items::IRON_SHEET, 1)],
+        time_ms: 500,
+    },
+    Recipe {
+        result: items::STORAGE_CHEST,
+        category: RecipeCategory::Logistics,
+        order: 7,
+        ingredients: &[(items::IRON_SHEET, 1)],
+        time_ms: 500,
+    },
+    Recipe {
+        result: items::REPAIR_PACK,
+        category: RecipeCategory::Production,
+        order: 3,
+        ingredients: &[(items::IRON_SHEET, 1), (items::COPPER_SHEET, 1)],
+        time_ms: 500,
+    },
+    Recipe {
+        result: items::AMMO,
+        category: RecipeCategory::Combat,
+        order: 2,
+        ingredients: &[(items::IRON_SHEET, 1)],
+        time_ms: 1000,
+    },
+    Recipe {
+        result: items::GUN_TURRET,
+        category: RecipeCategory::Combat,
+        order: 3,
+        ingredients: &[
+            (items::IRON_SHEET, 1),
+            (items::COPPER_SHEET, 1),
+            (items::IRON_GEAR, 2),
+        ],
+        time_ms: 3000,
+    },
+    Recipe {
+        result: items::WALL,
+        category: RecipeCategory::Combat,
+        order: 4,
+        ingredients: &[(items::IRON_SHEET, 1)],
+        time_ms: 500,
+    },
+    Recipe {
+        result: items::PISTOL,
+        category: RecipeCategory::Combat,
+        order: 1,
+        ingredients: &[(items::IRON_SHEET, 1), (items::COPPER_SHEET, 1)],
+        time_ms: 2000,
+    },
+];
+
+pub fn recipe_for(item_type: ItemType) -> Option<&'static Recipe> {
+    RECIPES.iter().find(|r| r.result == item_type)
+}
+
+/// Recipes that take the item as an ingredient.
+pub fn recipes_using(item_type: ItemType) -> Vec<&'static Recipe> {
+    RECIPES
+        .iter()
+        .filter(|r| r.ingredients.iter().any(|(t, _)| *t == item_type))
+        .collect()
+}
+
+/// Recipes of a category in the order they are shown.
+pub fn recipes_in(category: RecipeCategory) -> Vec<&'static Recipe> {
+    let mut recipes: Vec<&'static Recipe> =
+        RECIPES.iter().filter(|r| r.category == category).collect();
+    recipes.sort_by_key(|r| r.order);
+    recipes
+}
+
+/// Hand crafting of one item, together with the intermediates it needs.
+struct CraftOrder {
+    /// Identifies the order in the HUD, positions change as orders finish.
+    id: u32,
+    /// Recipes in the order they are crafted, the last one makes the ordered item.
+    steps: Vec<&'static Recipe>,
+    /// Items taken from the inventory when the order was queued, refunded when it is cancelled.
+    taken: Vec<(ItemType, usize)>,
+    step: usize,
+    elapsed: f32,
+}
+
+impl CraftOrder {
+    fn result(&self) -> ItemType {
+        self.steps[self.steps.len() - 1].result
+    }
+
+    fn time(&self) -> f32 {
+        self.steps.iter().map(|r| r.time()).sum()
+    }
+
+    fn progress(&self) -> f32 {
+        let done: f32 = self.steps[..self.step].iter().map(|r| r.time()).sum();
+        ((done + self.elapsed) / self.time()).clamp(0.0, 1.0)
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct CraftingQueue {
+    orders: VecDeque<CraftOrder>,
+    next_id: u32,
+    /// The HUD is rebuilt when orders are added or removed.
+    changed: bool,
+}
+
+impl CraftingQueue {
+    /// Removes the order and returns the items taken for it.
+    fn cancel(&mut self, id: u32) -> Option<Vec<(ItemType, usize)>> {
+        let index = self.orders.iter().position(|o| o.id == id)?;
+        let order = self.orders.remove(index)?;
+        self.changed = true;
+        Some(order.taken)
+    }
+}
+
+fn count(slot: &Option<(ItemType, usize)>) -> usize {
+    slot.map_or(0, |(_, c)| c)
+}
+
+fn take(inventory: &mut [Option<(ItemType, usize)>], item_type: ItemType) -> bool {
+    match inventory
+        .iter_mut()
+        .flatten()
+        .find(|(t, c)| *t == item_type && *c > 0)
+    {
+        Some((_, c)) => {
+            *c -= 1;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Adds the recipes needed to craft `item_type` to `steps`, missing intermediates first,
+/// and takes the used items out of `inventory`. Returns false if raw materials are missing.
+fn plan(
+    inventory: &mut [Option<(ItemType, usize)>],
+    item_type: ItemType,
+    steps: &mut Vec<&'static Recipe>,
+    depth: usize,
+) -> bool {
+    let Some(recipe) = recipe_for(item_type) else {
+        return false;
+    };
+    for &(ingredient, count) in recipe.ingredients {
+        for _ in 0..count {
+            if !take(inventory, ingredient)
+                && (depth >= MAX_INTERMEDIATE_DEPTH
+                    || !plan(inventory, ingredient, steps, depth + 1))
+            {
+                return false;
+            }
+        }
+    }
+    steps.push(recipe);
+    true
+}
+
+/// Returns whether the player has the materials to craft the item, intermediates included.
+pub fn can_craft(player: &Player, item_type: ItemType) -> bool {
+    let mut inventory = player.inventory.clone();
+    plan(&mut inventory, item_type, &mut Vec::new(), 0)
+}
+
+/// Queues crafting of one item, taking the ingredients from the player right away.
+/// Returns false if the player doesn't have the materials.
+pub fn queue_craft(queue: &mut CraftingQueue, player: &mut Player, item_type: ItemType) -> bool {
+    let mut inventory = player.inventory.clone();
+    let mut steps = Vec::new();
+    if !plan(&mut inventory, item_type, &mut steps, 0) {
+        return false;
+    }
+    let taken = player
+        .inventory
+        .iter()
+        .zip(inventory.iter())
+        .filter(|(before, after)| count(before) > count(after))
+        .filter_map(|(before, after)| before.map(|(t, c)| (t, c - count(after))))
+        .collect();
+    for slot in inventory.iter_mut() {
+        if count(slot) == 0 {
+            *slot = None;
+        }
+    }
+    player.inventory = inventory;
+    let id = queue.next_id;
+    queue.next_id = queue.next_id.wrapping_add(1);
+    queue.orders.push_back(CraftOrder {
+        id,
+        steps,
+        taken,
+        step: 0,
+        elapsed: 0.0,
+    });
+    queue.changed = true;
+    true
+}
+
+pub fn update_crafting(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut q_player: Query<(&mut Player, &Transform)>,
+    mut queue: ResMut<CraftingQueue>,
+    mut stats: ResMut<ProductionStats>,
+    time: Res<Time>,
+) {
+    let Some(order) = queue.orders.front_mut() else {
+        return;
+    };
+    order.elapsed += time.delta_seconds();
+    let recipe = order.steps[order.step];
+    if order.elapsed < recipe.time() {
+        return;
+    }
+    order.elapsed = 0.0;
+    order.step += 1;
+    for &(ingredient, count) in recipe.ingredients {
+        stats.record_consumed(ingredient, count as u32);
+    }
+    stats.record_produced(recipe.result, 1);
+    if order.step < order.steps.len() {
+        return;
+    }
+
+    // intermediates went into the next step, only the ordered item goes to the player
+    let (mut player, transform) = q_player.single_mut();
+    let pos = transform.translation.truncate();
+    actions::give_or_drop(
+        &mut commands,
+        &asset_server,
+        &mut player,
+        recipe.result,
+        (pos.x / 32.0 + 0.5).floor() as i32,
+        (pos.y / 32.0 + 0.5).floor() as i32,
+    );
+    queue.orders.pop_front();
+    queue.changed = true;
+}
+
+#[derive(Component)]
+pub struct CraftingQueueHud;
+
+#[derive(Component)]
+pub struct QueuedCraft {
+    id: u32,
+}
+
+#[derive(Component)]
+pub struct CraftingProgressBar;
+
+pub fn setup_crafting_hud(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(10.0),
+                bottom: Val::Px(10.0),
+                flex_direction: FlexDirection::Row,
+                ..default()
+            },
+            ..default()
+        },
+        CraftingQueueHud,
+    ));
+}
+
+/// Shows the queued orders with their crafting time, and the progress of the first one.
+pub fn update_crafting_hud(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut queue: ResMut<CraftingQueue>,
+    q_hud: Query<Entity, With<CraftingQueueHud>>,
+    mut q_progress_bar: Query<&mut Style, With<CraftingProgressBar>>,
+) {
+    if let Some(order) = queue.orders.front() {
+        for mut style in q_progress_bar.iter_mut() {
+            style.width = Val::Percent(order.progress() * 100.0);
+        }
+    }
+    if !queue.changed {
+        return;
+    }
+    queue.changed = false;
+
+    let hud = q_hud.single();
+    commands.entity(hud).despawn_descendants();
+    commands.entity(hud).with_children(|parent| {
+        for (index, order) in queue.orders.iter().enumerate() {
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: Style {
+                            width: Val::Px(36.0),
+                            height: Val::Px(36.0),
+                            padding: UiRect::all(Val::Px(2.)),
+                            margin: UiRect::all(Val::Px(1.)),
+                            ..default()
+                        },
+                        background_color: BackgroundColor(COLOR_QUEUE_BG),
+                        ..default()
+                    },
+                    QueuedCraft { id: order.id },
+                ))
+                .with_children(|parent| {
+                    parent
+                        .spawn((
+                            NodeBundle {
+                                style: Style {
+                                    width: Val::Px(32.0),
+                                    height: Val::Px(32.0),
+                                    justify_content: JustifyContent::End,
+                                    align_items: AlignItems::End,
+                                    ..default()
+                                },
+                                ..default()
+                            },
+                            UiImage::new(asset_server.load(format!(
+                                "textures/items/{}.png",
+                                order.result().texture_name
+                            ))),
+                        ))
+                        .with_children(|parent| {
+                            ui::create_outlined_text(parent, format!("{:.1}s", order.time()));
+                        });
+                    if index == 0 {
+                        parent.spawn((
+                            NodeBundle {
+                                style: Style {
+                                    position_type: PositionType::Absolute,
+                                    left: Val::Px(0.0),
+                                    bottom: Val::Px(0.0),
+                                    width: Val::Percent(order.progress() * 100.0),
+                                    height: Val::Px(3.0),
+                                    ..default()
+                                },
+                                background_color: BackgroundColor(COLOR_PROGRESS),
+                                ..default()
+                            },
+                            CraftingProgressBar,
+                        ));
+                    }
+                });
+        }
+    });
+}
+
+/// Clicking a queued order cancels it and gives back everything taken for it.
+pub fn handle_crafting_queue_clicks(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut q_player: Query<(&mut Player, &Transform)>,
+    mut queue: ResMut<CraftingQueue>,
+    q_queued: Query<(&QueuedCraft, &Interaction), Changed<Interaction>>,
+) {
+    for (queued, interaction) in q_queued.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        // the order may have finished in the meantime
+        let Some(taken) = queue.cancel(queued.id) else {
+            continue;
+        };
+        let (mut player, transform) = q_player.single_mut();
+        let pos = transform.translation.truncate();
+        for (item_type, count) in taken {
+            for _ in 0..count {
+                actions::give_or_drop(
+                    &mut commands,
+                    &asset_server,
+                    &mut player,
+                    item_type,
+                    (pos.x / 32.0 + 0.5).floor() as i32,
+                    (pos.y / 32.0 + 0.5).floor() as i32,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn total(player: &Player, item_type: ItemType) -> usize {
+        player
+            .inventory
+            .iter()
+            .flatten()
+            .filter(|(t, _)| *t == item_type)
+            .map(|(_, c)| c)
+            .sum()
+    }
+
+    #[test]
+    fn crafts_missing_intermediates_first() {
+        let mut queue = CraftingQueue::default();
+        let mut player = Player::new(&[(items::IRON_SHEET, 5)], None);
+
+        assert!(queue_craft(&mut queue, &mut player, items::BELT));
+
+        let order = &queue.orders[0];
+        let results: Vec<ItemType> = order.steps.iter().map(|r| r.result).collect();
+        assert_eq!(results, vec![items::IRON_GEAR, items::BELT]);
+        assert_eq!(order.taken, vec![(items::IRON_SHEET, 3)]);
+        assert_eq!(total(&player, items::IRON_SHEET), 2);
+    }
+
+    #[test]
+    fn uses_intermediates_from_the_inventory() {
+        let mut queue = CraftingQueue::default();
+        let mut player = Player::new(&[(items::IRON_SHEET, 5), (items::IRON_GEAR, 1)], None);
+
+        assert!(queue_craft(&mut queue, &mut player, items::BELT));
+
+        assert_eq!(queue.orders[0].steps.len(), 1);
+        assert_eq!(total(&player, items::IRON_GEAR), 0);
+        assert_eq!(total(&player, items::IRON_SHEET), 4);
+    }
+
+    #[test]
+    fn failing_leaves_the_inventory_unchanged() {
+        let mut queue = CraftingQueue::default();
+        let mut player = Player::new(&[(items::IRON_SHEET, 2), (items::BELT, 1)], None);
+        let before = player.inventory.clone();
+
+        // a fast belt needs two gears, which take four sheets
+        assert!(!can_craft(&player, items::FAST_BELT));
+        assert!(!queue_craft(&mut queue, &mut player, items::FAST_BELT));
+
+        assert!(queue.orders.is_empty());
+        assert_eq!(player.inventory, before);
+    }
+
+    #[test]
+    fn cancel_refunds_everything_taken() {
+        let mut queue = CraftingQueue::default();
+        let mut player = Player::new(&[(items::IRON_SHEET, 3), (items::BELT, 1)], None);
+        assert!(queue_craft(&mut queue, &mut player, items::RAIL));
+        assert!(queue_craft(&mut queue, &mut player, items::FURNACE));
+        let rail_id = queue.orders[0].id;
+        let furnace_id = queue.orders[1].id;
+
+        // the first order finishing must not shift which order is cancelled
+        queue.orders.pop_front();
+        let taken = queue.cancel(furnace_id).unwrap();
+        assert_eq!(taken, vec![(items::IRON_SHEET, 1)]);
+        assert!(queue.cancel(rail_id).is_none());
+
+        for (item_type, count) in taken {
+            assert_eq!(player.add_items(item_type, count), 0);
+        }
+        assert_eq!(total(&player, items::IRON_SHEET), 2);
+        assert_eq!(total(&player, items::BELT), 1);
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::VecDeque;
+
+use bevy::{math::vec2, prelude::*};
+
+use crate::{
+    actions::{self, ContentsQuery, PlayerReach},
+    rails::RailNetwork,
+    selection::AreaSelected,
+    GameWorld, InputState, PlacedTile, Player, SelectionTool,
+};
+
+const DECONSTRUCTION_INTERVAL: f32 = 0.1;
+
+/// Cells marked for deconstruction, removed one after another.
+#[derive(Resource)]
+pub struct DeconstructionQueue {
+    cells: VecDeque<(i32, i32)>,
+    timer: Timer,
+}
+
+impl Default for DeconstructionQueue {
+    fn default() -> Self {
+        Self {
+            cells: VecDeque::new(),
+            timer: Timer::from_seconds(DECONSTRUCTION_INTERVAL, TimerMode::Repeating),
+        }
+    }
+}
+
+pub fn handle_deconstruction_planner(
+    mut commands: Commands,
+    mut input_state: ResMut<InputState>,
+    mut area_selected: EventReader<AreaSelected>,
+    mut game_world: ResMut<GameWorld>,
+    mut queue: ResMut<DeconstructionQueue>,
+) {
+    if input_state.toggling_deconstruction_tool {
+        if input_state.selection_tool == Some(SelectionTool::Deconstruction) {
+            input_state.selection_tool = None;
+        } else {
+            input_state.selection_tool = Some(SelectionTool::Deconstruction);
+            input_state.item_in_hand = None;
+        }
+    }
+
+    for area in area_selected.read() {
+        if area.tool != SelectionTool::Deconstruction {
+            continue;
+        }
+        // ghosts are not built yet, so they can go right away
+        game_world.ghosts.retain(|&(x, y), ghost| {
+            let inside = area.contains(x, y);
+            if inside {
+                commands.entity(*ghost).despawn();
+            }
+            !inside
+        });
+
+        let mut cells: Vec<(i32, i32)> = game_world
+            .tiles
+            .keys()
+            .copied()
+            .filter(|&(x, y)| area.contains(x, y) && !queue.cells.contains(&(x, y)))
+            .collect();
+        cells.sort_by_key(|&(x, y)| (y, x));
+        queue.cells.extend(cells);
+    }
+}
+
+pub fn deconstruct_marked_tiles(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut q_player: Query<(&mut Player, &Transform, &PlayerReach)>,
+    mut game_world: ResMut<GameWorld>,
+    rail_network: Res<RailNetwork>,
+    q_tiles: Query<(Entity, &PlacedTile)>,
+    q_contents: ContentsQuery,
+    mut queue: ResMut<DeconstructionQueue>,
+    mut gizmos: Gizmos,
+    time: Res<Time>,
+) {
+    for &(x, y) in queue.cells.iter() {
+        let center = vec2(x as f32, y as f32) * 32.0;
+        let color = Color::srgb(0.9, 0.2, 0.2);
+        gizmos.line_2d(
+            center + vec2(-10.0, -10.0),
+            center + vec2(10.0, 10.0),
+            color,
+        );
+        gizmos.line_2d(
+            center + vec2(-10.0, 10.0),
+            center + vec2(10.0, -10.0),
+            color,
+        );
+    }
+
+    if !queue.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let Some((x, y)) = queue.cells.pop_front() else {
+        return;
+    };
+    let (mut player, transform, reach) = q_player.single_mut();
+    let in_reach =
+        (vec2(x as f32, y as f32) * 32.0).distance(transform.translation.truncate()) <= reach.build;
+    // tiles below trains or out of reach stay marked and are tried again after the rest
+    if rail_network.is_occupied(x, y) || !in_reach {
+        queue.cells.push_back((x, y));
+        return;
+    }
+    actions::deconstruct_tile(
+        &mut commands,
+        &asset_server,
+        &mut player,
+        &mut game_world,
+        &q_tiles,
+        &q_contents,
+        (x, y),
+    );
+}
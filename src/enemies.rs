@@ -0,0 +1,292 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, VecDeque},
+};
+
+use bevy::{math::vec3, prelude::*, sprite::MaterialMesh2dBundle, utils::HashMap};
+use bevy_prng::ChaCha8Rng;
+use bevy_rand::prelude::GlobalEntropy;
+use rand::Rng;
+
+use crate::{health::DamageEvent, tiles, GameWorld, Health, Layer, PlacedTile, Player};
+
+const MAP_RADIUS: i32 = 100;
+const NUM_SPAWNERS: usize = 12;
+const MIN_SPAWNER_DIST: i32 = 40;
+const ENEMY_SPEED: f32 = 48.0;
+/// Walking through a building means destroying it first, which takes a while.
+/// This is the cost for a building with 100 health.
+const ATTACK_COST: u32 = 20;
+const ATTACK_INTERVAL: f32 = 1.0;
+const ATTACK_DAMAGE: f32 = 10.0;
+const ATTACK_RANGE: f32 = 20.0;
+const ENEMY_HEALTH: f32 = 30.0;
+pub const ENEMY_RADIUS: f32 = 6.0;
+const MAX_SEARCHED_CELLS: usize = 50_000;
+const WAVE_INTERVAL: f32 = 120.0;
+const MAX_WAVE_SIZE: u32 = 20;
+
+#[derive(Component)]
+pub struct EnemySpawner;
+
+#[derive(Component)]
+pub struct Enemy {
+    path: VecDeque<(i32, i32)>,
+    /// Limits how often a new path is searched for, since that is expensive.
+    repath_timer: Timer,
+    attack_timer: Timer,
+}
+
+#[derive(Resource)]
+pub struct EnemyAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<ColorMaterial>,
+}
+
+#[derive(Resource)]
+pub struct AttackWaves {
+    timer: Timer,
+    wave: u32,
+}
+
+impl Default for AttackWaves {
+    fn default() -> Self {
+        Self {
+            timer: Timer::from_seconds(WAVE_INTERVAL, TimerMode::Repeating),
+            wave: 0,
+        }
+    }
+}
+
+fn to_cell(pos: Vec2) -> (i32, i32) {
+    (
+        (pos.x / 32.0 + 0.5).floor() as i32,
+        (pos.y / 32.0 + 0.5).floor() as i32,
+    )
+}
+
+fn heuristic(a: (i32, i32), b: (i32, i32)) -> u32 {
+    (a.0 - b.0).unsigned_abs() + (a.1 - b.1).unsigned_abs()
+}
+
+/// A* search over the tile grid. Cells with buildings can be passed, but only by attacking them.
+/// Walls block the way unless `through_walls` is set, which is used when there is no other route.
+/// The returned path does not include `start`.
+pub fn find_path(
+    game_world: &GameWorld,
+    start: (i32, i32),
+    goal: (i32, i32),
+    through_walls: bool,
+) -> Option<VecDeque<(i32, i32)>> {
+    let mut open = BinaryHeap::new();
+    let mut costs = HashMap::new();
+    let mut came_from = HashMap::new();
+
+    open.push(Reverse((heuristic(start, goal), start)));
+    costs.insert(start, 0);
+
+    let mut searched = 0;
+    while let Some(Reverse((_, cell))) = open.pop() {
+        if cell == goal {
+            let mut path = VecDeque::new();
+            let mut cell = goal;
+            while cell != start {
+                path.push_front(cell);
+                cell = came_from[&cell];
+            }
+            return Some(path);
+        }
+
+        searched += 1;
+        if searched > MAX_SEARCHED_CELLS {
+            return None;
+        }
+
+        let cost = costs[&cell];
+        for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let next = (cell.0 + dx, cell.1 + dy);
+            if next.0.abs() > MAP_RADIUS || next.1.abs() > MAP_RADIUS {
+                continue;
+            }
+            // sturdier buildings like walls are avoided when possible
+            let step_cost = match game_world.tiles.get(&next) {
+                Some(tile) if tile.tile_type == tiles::WALL && !through_walls && next != goal => {
+                    continue;
+                }
+                Some(tile) => ATTACK_COST * tile.tile_type.max_health / 100,
+                None => 1,
+            };
+            let new_cost = cost + step_cost;
+            if costs.get(&next).is_none_or(|&c| new_cost < c) {
+                costs.insert(next, new_cost);
+                came_from.insert(next, cell);
+                open.push(Reverse((new_cost + heuristic(next, goal), next)));
+            }
+        }
+    }
+    None
+}
+
+pub fn spawn_enemy_spawners(
+    mut commands: Commands,
+    mut game_world: ResMut<GameWorld>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut rng: ResMut<GlobalEntropy<ChaCha8Rng>>,
+) {
+    let spawner_mesh = meshes.add(Rectangle::new(28.0, 28.0));
+    let spawner_material = materials.add(Color::srgb(0.4, 0.05, 0.1));
+
+    let mut placed = 0;
+    while placed < NUM_SPAWNERS {
+        let x = rng.gen_range(-MAP_RADIUS + 5..MAP_RADIUS - 5);
+        let y = rng.gen_range(-MAP_RADIUS + 5..MAP_RADIUS - 5);
+        // the cell is reserved so nothing can be built on the spawner
+        if x.abs().max(y.abs()) < MIN_SPAWNER_DIST || !game_world.reserved.insert((x, y)) {
+            continue;
+        }
+        placed += 1;
+
+        commands.spawn((
+            EnemySpawner,
+            MaterialMesh2dBundle {
+                mesh: spawner_mesh.clone().into(),
+                material: spawner_material.clone(),
+                transform: Transform::from_translation(vec3(
+                    x as f32 * 32.0,
+                    y as f32 * 32.0,
+                    Layer::Tile.depth(),
+                )),
+                ..default()
+            },
+        ));
+    }
+
+    commands.insert_resource(EnemyAssets {
+        mesh: meshes.add(Circle::new(ENEMY_RADIUS)),
+        material: materials.add(Color::srgb(0.9, 0.15, 0.1)),
+    });
+}
+
+pub fn schedule_attack_waves(
+    mut commands: Commands,
+    enemy_assets: Res<EnemyAssets>,
+    mut attack_waves: ResMut<AttackWaves>,
+    q_spawners: Query<&Transform, With<EnemySpawner>>,
+    game_world: Res<GameWorld>,
+    mut rng: ResMut<GlobalEntropy<ChaCha8Rng>>,
+    time: Res<Time>,
+) {
+    if !attack_waves.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    // there is no factory to attack yet
+    if game_world.tiles.is_empty() {
+        return;
+    }
+    let spawners: Vec<&Transform> = q_spawners.iter().collect();
+    if spawners.is_empty() {
+        return;
+    }
+
+    attack_waves.wave += 1;
+    let spawner = spawners[rng.gen_range(0..spawners.len())];
+    let wave_size = (1 + attack_waves.wave).min(MAX_WAVE_SIZE);
+
+    for _ in 0..wave_size {
+        let offset = Vec2::new(rng.gen_range(-24.0..24.0), rng.gen_range(-24.0..24.0));
+        commands.spawn((
+            Enemy {
+                path: VecDeque::new(),
+                repath_timer: Timer::from_seconds(1.0, TimerMode::Once),
+                attack_timer: Timer::from_seconds(ATTACK_INTERVAL, TimerMode::Repeating),
+            },
+            Health::new(ENEMY_HEALTH),
+            MaterialMesh2dBundle {
+                mesh: enemy_assets.mesh.clone().into(),
+                material: enemy_assets.material.clone(),
+                transform: Transform::from_translation(
+                    (spawner.translation.truncate() + offset).extend(Layer::Enemy.depth()),
+                ),
+                ..default()
+            },
+        ));
+    }
+}
+
+pub fn update_enemies(
+    mut q_enemies: Query<(&mut Enemy, &mut Transform), Without<Player>>,
+    q_buildings: Query<(Entity, &PlacedTile), With<Health>>,
+    q_player: Query<(Entity, &Transform), With<Player>>,
+    game_world: Res<GameWorld>,
+    mut damage_events: EventWriter<DamageEvent>,
+    time: Res<Time>,
+) {
+    let player = q_player.get_single().ok();
+    for (mut enemy, mut transform) in q_enemies.iter_mut() {
+        let pos = transform.translation.truncate();
+        let cell = to_cell(pos);
+
+        // the player is attacked whenever they come close
+        if let Some((player_entity, player_transform)) = player {
+            if player_transform.translation.truncate().distance(pos) < ATTACK_RANGE {
+                if enemy.attack_timer.tick(time.delta()).just_finished() {
+                    damage_events.send(DamageEvent {
+                        target: player_entity,
+                        amount: ATTACK_DAMAGE,
+                    });
+                }
+                continue;
+            }
+        }
+
+        if enemy.path.is_empty() {
+            if !enemy.repath_timer.tick(time.delta()).finished() {
+                continue;
+            }
+            enemy.repath_timer.reset();
+
+            let target = game_world
+                .tiles
+                .keys()
+                .min_by_key(|&&t| heuristic(t, cell))
+                .copied()
+                .or_else(|| player.map(|(_, tr)| to_cell(tr.translation.truncate())));
+            // walls are only attacked when they can't be walked around
+            if let Some(path) = target.and_then(|target| {
+                find_path(&game_world, cell, target, false)
+                    .or_else(|| find_path(&game_world, cell, target, true))
+            }) {
+                enemy.path = path;
+            }
+            continue;
+        }
+
+        let next = enemy.path[0];
+        if game_world.tiles.contains_key(&next) {
+            if enemy.attack_timer.tick(time.delta()).just_finished() {
+                if let Some((entity, _)) = q_buildings
+                    .iter()
+                    .find(|(_, tile)| (tile.x, tile.y) == next)
+                {
+                    damage_events.send(DamageEvent {
+                        target: entity,
+                        amount: ATTACK_DAMAGE,
+                    });
+                }
+            }
+            continue;
+        }
+        enemy.attack_timer.reset();
+
+        let target = Vec2::new(next.0 as f32 * 32.0, next.1 as f32 * 32.0);
+        let delta = target - pos;
+        let step = ENEMY_SPEED * time.delta_seconds();
+        if delta.length() > step {
+            transform.translation += (delta.normalize() * step).extend(0.0);
+        } else {
+            transform.translation = target.extend(Layer::Enemy.depth());
+            enemy.path.pop_front();
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use bevy::{math::vec2, prelude::*, window::PrimaryWindow};
+
+use crate::{
+    actions::PlayerReach, cursor_cell, items, sprites, GameWorld, Health, InputState, PlacedTile,
+    Player,
+};
+
+const REMNANTS_LIFETIME: f32 = 60.0;
+const REPAIR_SPEED: f32 = 50.0;
+const REPAIR_PACK_DURABILITY: f32 = 200.0;
+
+#[derive(Event)]
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: f32,
+}
+
+#[derive(Component)]
+pub struct Remnants {
+    timer: Timer,
+}
+
+/// How much health the repair pack currently in use can still restore.
+#[derive(Resource, Default)]
+pub struct RepairPack {
+    durability: f32,
+}
+
+pub fn apply_damage(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut damage_events: EventReader<DamageEvent>,
+    mut q_health: Query<(
+        &mut Health,
+        &mut Transform,
+        Option<&PlacedTile>,
+        Has<Player>,
+    )>,
+    q_tiles: Query<(Entity, &PlacedTile)>,
+    mut game_world: ResMut<GameWorld>,
+) {
+    for event in damage_events.read() {
+        let Ok((mut health, mut transform, tile, is_player)) = q_health.get_mut(event.target)
+        else {
+            continue;
+        };
+        if health.current <= 0.0 {
+            // already destroyed by an earlier event
+            continue;
+        }
+        health.current -= event.amount;
+        if health.current > 0.0 {
+            continue;
+        }
+
+        if is_player {
+            // the player respawns in the middle of the map
+            health.current = health.max;
+            transform.translation.x = 0.0;
+            transform.translation.y = 0.0;
+        } else if let Some(tile) = tile {
+            game_world.tiles.remove(&(tile.x, tile.y));
+            for (entity, _) in q_tiles
+                .iter()
+                .filter(|(_, t)| t.x == tile.x && t.y == tile.y)
+            {
+                commands.entity(entity).despawn_recursive();
+            }
+            commands.spawn((
+                sprites::create_remnants_sprite(&asset_server, tile),
+                Remnants {
+                    timer: Timer::from_seconds(REMNANTS_LIFETIME, TimerMode::Once),
+                },
+            ));
+        } else {
+            commands.entity(event.target).despawn_recursive();
+        }
+    }
+}
+
+pub fn update_remnants(
+    mut commands: Commands,
+    mut q_remnants: Query<(Entity, &mut Remnants, &mut Sprite)>,
+    time: Res<Time>,
+) {
+    for (entity, mut remnants, mut sprite) in q_remnants.iter_mut() {
+        if remnants.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+        } else {
+            sprite
+                .color
+                .set_alpha(0.8 * remnants.timer.fraction_remaining());
+        }
+    }
+}
+
+pub fn draw_health_bars(mut gizmos: Gizmos, q_health: Query<(&Health, &GlobalTransform)>) {
+    for (health, transform) in q_health.iter() {
+        if health.current >= health.max {
+            continue;
+        }
+        let start = transform.translation().truncate() + vec2(-12.0, 18.0);
+        let fraction = (health.current / health.max).clamp(0.0, 1.0);
+        gizmos.line_2d(start, start + vec2(24.0, 0.0), Color::srgb(0.4, 0.0, 0.0));
+        gizmos.line_2d(
+            start,
+            start + vec2(24.0 * fraction, 0.0),
+            Color::srgb(0.1, 0.9, 0.1),
+        );
+    }
+}
+
+/// Holding the left mouse button over a damaged building with a repair pack in hand restores its health.
+pub fn repair_buildings(
+    q_windows: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<(&Camera, &GlobalTransform)>,
+    mut q_player: Query<(&mut Player, &Transform, &PlayerReach)>,
+    mut input_state: ResMut<InputState>,
+    mut repair_pack: ResMut<RepairPack>,
+    mut q_buildings: Query<(&PlacedTile, &mut Health)>,
+    time: Res<Time>,
+) {
+    if input_state.item_in_hand != Some(items::REPAIR_PACK) || input_state.drag_start.is_none() {
+        return;
+    }
+    let (camera, camera_transform) = q_camera.single();
+    let Some((xx, yy)) = cursor_cell(q_windows.single(), camera, camera_transform) else {
+        return;
+    };
+
+    let (mut player, player_transform, reach) = q_player.single_mut();
+    let distance =
+        (vec2(xx as f32, yy as f32) * 32.0).distance(player_transform.translation.truncate());
+    if distance > reach.build {
+        return;
+    }
+    let Some((_, mut health)) = q_buildings
+        .iter_mut()
+        .find(|(t, h)| t.x == xx && t.y == yy && h.current < h.max)
+    else {
+        return;
+    };
+
+    if repair_pack.durability <= 0.0 {
+        if !player.decrement_inventory(items::REPAIR_PACK) {
+            input_state.item_in_hand = None;
+            return;
+        }
+        repair_pack.durability += REPAIR_PACK_DURABILITY;
+    }
+
+    let amount = (REPAIR_SPEED * time.delta_seconds())
+        .min(health.max - health.current)
+        .min(repair_pack.durability);
+    health.current += amount;
+    repair_pack.durability -= amount;
+}
@@ -0,0 +1,218 @@
+use bevy::prelude::*;
+
+use crate::{
+    actions::{self, ContentsQuery},
+    placement::Placement,
+    rails::RailNetwork,
+    robots::LogisticChest,
+    GameWorld, InputState, PlacedTile, Player, ResourceTile, TileSettings,
+};
+
+/// Number of undoable steps that are kept.
+const MAX_HISTORY: usize = 100;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BuildActionKind {
+    Placed,
+    Deleted,
+    /// The tile was turned from the given rotation to the one it has in the action.
+    Rotated {
+        from: u8,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct BuildAction {
+    pub kind: BuildActionKind,
+    pub tile: PlacedTile,
+    pub settings: Option<TileSettings>,
+}
+
+/// Undo and redo stacks. Everything recorded while a mouse button is held down
+/// forms a single step, so a whole dragged line is undone at once.
+#[derive(Resource, Default)]
+pub struct BuildHistory {
+    undo: Vec<Vec<BuildAction>>,
+    redo: Vec<Vec<BuildAction>>,
+    pending: Vec<BuildAction>,
+}
+
+impl BuildHistory {
+    pub fn record(&mut self, action: BuildAction) {
+        self.pending.push(action);
+    }
+
+    fn finish_step(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        self.undo.push(std::mem::take(&mut self.pending));
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+}
+
+/// Places a tile again, taking the item from the player. Returns false if that is not possible.
+fn place(
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    player: &mut Player,
+    game_world: &mut GameWorld,
+    rail_network: &mut RailNetwork,
+    q_resource_tiles: &Query<&ResourceTile>,
+    action: &BuildAction,
+) -> bool {
+    let tile = &action.tile;
+    if game_world.tiles.contains_key(&(tile.x, tile.y))
+        || !player.decrement_inventory(tile.tile_type.item_to_drop)
+    {
+        return false;
+    }
+    if let Some(ghost) = game_world.ghosts.remove(&(tile.x, tile.y)) {
+        commands.entity(ghost).despawn();
+    }
+    game_world.tiles.insert((tile.x, tile.y), tile.clone());
+
+    let resource = q_resource_tiles
+        .iter()
+        .find(|t| t.x == tile.x && t.y == tile.y)
+        .map(|t| t.resource_type);
+    let entity = actions::spawn_tile(commands, asset_server, rail_network, tile.clone(), resource);
+    if let Some(settings) = action.settings {
+        actions::apply_tile_settings(commands, entity, settings);
+    }
+    true
+}
+
+/// Removes a tile and gives its item and contents back to the player.
+/// Returns false if the tile is gone or changed.
+fn remove(
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    player: &mut Player,
+    game_world: &mut GameWorld,
+    q_tiles: &Query<(Entity, &PlacedTile)>,
+    q_contents: &ContentsQuery,
+    tile: &PlacedTile,
+) -> bool {
+    if game_world.tiles.get(&(tile.x, tile.y)) != Some(tile) {
+        return false;
+    }
+    actions::deconstruct_tile(
+        commands,
+        asset_server,
+        player,
+        game_world,
+        q_tiles,
+        q_contents,
+        (tile.x, tile.y),
+    )
+    .is_some()
+}
+
+pub fn handle_undo_redo(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut q_player: Query<&mut Player>,
+    input_state: Res<InputState>,
+    mut history: ResMut<BuildHistory>,
+    mut game_world: ResMut<GameWorld>,
+    mut rail_network: ResMut<RailNetwork>,
+    q_tiles: Query<(Entity, &PlacedTile)>,
+    q_chests: Query<&LogisticChest>,
+    q_contents: ContentsQuery,
+    q_resource_tiles: Query<&ResourceTile>,
+    placement: Placement,
+    mut tile_rotated: EventWriter<actions::TileRotated>,
+) {
+    if input_state.drag_start.is_none() && !input_state.deleting_tile {
+        history.finish_step();
+    }
+
+    let undoing = input_state.undoing;
+    if !undoing && !input_state.redoing {
+        return;
+    }
+    let step = if undoing {
+        history.undo.pop()
+    } else {
+        history.redo.pop()
+    };
+    let Some(mut step) = step else {
+        return;
+    };
+
+    let mut player = q_player.single_mut();
+    if undoing {
+        step.reverse();
+    }
+    let mut done = Vec::new();
+    for mut action in step {
+        let place_again = (action.kind == BuildActionKind::Deleted) == undoing;
+        let applied = if let BuildActionKind::Rotated { from } = action.kind {
+            let (current, target) = if undoing {
+                (action.tile.rotation, from)
+            } else {
+                (from, action.tile.rotation)
+            };
+            let tile = &action.tile;
+            game_world
+                .tiles
+                .get(&(tile.x, tile.y))
+                .is_some_and(|t| t.tile_type == tile.tile_type && t.rotation == current)
+                && actions::rotate_tile(
+                    &mut game_world,
+                    &rail_network,
+                    &mut tile_rotated,
+                    tile.x,
+                    tile.y,
+                    target,
+                )
+        } else if place_again {
+            let tile = &action.tile;
+            placement.is_valid(&game_world, tile.tile_type, tile.x, tile.y)
+                && place(
+                    &mut commands,
+                    &asset_server,
+                    &mut player,
+                    &mut game_world,
+                    &mut rail_network,
+                    &q_resource_tiles,
+                    &action,
+                )
+        } else if rail_network.is_occupied(action.tile.x, action.tile.y) {
+            false
+        } else {
+            let settings =
+                actions::tile_settings(&q_tiles, &q_chests, (action.tile.x, action.tile.y));
+            let removed = remove(
+                &mut commands,
+                &asset_server,
+                &mut player,
+                &mut game_world,
+                &q_tiles,
+                &q_contents,
+                &action.tile,
+            );
+            if removed {
+                action.settings = settings;
+            }
+            removed
+        };
+        if applied {
+            done.push(action);
+        }
+    }
+    // only what could actually be changed can be changed back
+    if done.is_empty() {
+        return;
+    }
+    if undoing {
+        done.reverse();
+        history.redo.push(done);
+    } else {
+        history.undo.push(done);
+    }
+}
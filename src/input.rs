@@ -1,6 +1,9 @@
-use bevy::{prelude::*, window::PrimaryWindow};
+use bevy::{math::vec2, prelude::*, window::PrimaryWindow};
 
-use crate::{InputState, Player, PLAYER_SPEED};
+use crate::{
+    cursor_world_pos, updates::belt_speed, GameWorld, InputState, Player, PLAYER_RADIUS,
+    PLAYER_SPEED,
+};
 
 pub fn update_camera(
     mut camera: Query<&mut Transform, (With<Camera2d>, Without<Player>)>,
@@ -25,6 +28,7 @@ pub fn update_camera(
 
 pub fn mouse_button_events(
     buttons: Res<ButtonInput<MouseButton>>,
+    kb_input: Res<ButtonInput<KeyCode>>,
     q_windows: Query<&Window, With<PrimaryWindow>>,
     mut input_state: ResMut<InputState>,
     q_camera: Query<(&Camera, &GlobalTransform)>,
@@ -33,13 +37,8 @@ pub fn mouse_button_events(
         return;
     }
 
-    let window = q_windows.single();
     let (camera, camera_transform) = q_camera.single();
-
-    let mouse_pos = window
-        .cursor_position()
-        .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor))
-        .map(|ray| ray.origin.truncate());
+    let mouse_pos = cursor_world_pos(q_windows.single(), camera, camera_transform);
 
     if let Some(pos) = mouse_pos {
         if buttons.pressed(MouseButton::Left) {
@@ -47,16 +46,46 @@ pub fn mouse_button_events(
         } else {
             input_state.drag_start = None;
         }
-        input_state.deleting_tile = buttons.pressed(MouseButton::Right);
+        // shift + right click copies settings instead of deleting
+        let shift = kb_input.pressed(KeyCode::ShiftLeft);
+        input_state.deleting_tile = buttons.pressed(MouseButton::Right) && !shift;
+        input_state.copying_settings = shift && buttons.just_pressed(MouseButton::Right);
+        input_state.pasting_settings = shift
+            && buttons.pressed(MouseButton::Left)
+            && input_state.item_in_hand.is_none()
+            && input_state.selection_tool.is_none();
     } else {
         // mouse is outside the window
         input_state.drag_start = None;
+        input_state.copying_settings = false;
+        input_state.pasting_settings = false;
     }
 }
 
+/// Returns whether the player at this position would overlap a tile it can't walk over.
+fn collides(game_world: &GameWorld, pos: Vec2) -> bool {
+    let x = (pos.x / 32.0 + 0.5).floor() as i32;
+    let y = (pos.y / 32.0 + 0.5).floor() as i32;
+    (x - 1..=x + 1)
+        .flat_map(|xx| (y - 1..=y + 1).map(move |yy| (xx, yy)))
+        .filter(|cell| {
+            game_world
+                .tiles
+                .get(cell)
+                .is_some_and(|t| !t.tile_type.walkable)
+        })
+        .any(|(xx, yy)| {
+            let center = vec2(xx as f32, yy as f32) * 32.0;
+            let closest = pos.clamp(center - 16.0, center + 16.0);
+            closest.distance_squared(pos) < PLAYER_RADIUS * PLAYER_RADIUS
+        })
+}
+
 pub fn move_player(
     mut player: Query<&mut Transform, With<Player>>,
+    game_world: Res<GameWorld>,
     time: Res<Time>,
+    fixed_time: Res<Time<Fixed>>,
     kb_input: Res<ButtonInput<KeyCode>>,
     mut app_exit_events: ResMut<Events<bevy::app::AppExit>>,
     mut input_state: ResMut<InputState>,
@@ -87,21 +116,72 @@ pub fn move_player(
         app_exit_events.send(bevy::app::AppExit::Success);
     }
 
-    input_state.dropping_items = kb_input.pressed(KeyCode::KeyZ);
+    let ctrl = kb_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
 
-    if kb_input.just_pressed(KeyCode::KeyR) {
-        input_state.rotation += 3;
-        input_state.rotation %= 4;
-    }
+    input_state.dropping_items = kb_input.pressed(KeyCode::KeyZ) && !ctrl;
+
+    input_state.undoing = ctrl && kb_input.just_pressed(KeyCode::KeyZ);
+
+    input_state.redoing = ctrl && kb_input.just_pressed(KeyCode::KeyY);
+
+    input_state.rotating = kb_input.just_pressed(KeyCode::KeyR);
 
     input_state.toggling_inventory_visible = kb_input.just_pressed(KeyCode::KeyE);
 
     input_state.picking_items = kb_input.pressed(KeyCode::KeyF);
 
-    if kb_input.just_pressed(KeyCode::KeyQ) {
-        input_state.item_in_hand = None;
+    input_state.placing_ghosts = kb_input.pressed(KeyCode::ShiftLeft);
+
+    input_state.setting_request = kb_input.just_pressed(KeyCode::KeyT);
+
+    input_state.toggling_schedule_editor = kb_input.just_pressed(KeyCode::KeyG);
+
+    input_state.removing_schedule_entry = kb_input.just_pressed(KeyCode::Backspace);
+
+    input_state.shooting = kb_input.pressed(KeyCode::Space);
+
+    input_state.toggling_stats_visible = kb_input.just_pressed(KeyCode::KeyP);
+
+    input_state.toggling_blueprint_tool = kb_input.just_pressed(KeyCode::KeyB);
+
+    input_state.toggling_deconstruction_tool = kb_input.just_pressed(KeyCode::KeyC);
+
+    input_state.toggling_upgrade_tool = kb_input.just_pressed(KeyCode::KeyU);
+
+    input_state.exporting_blueprint = kb_input.just_pressed(KeyCode::KeyX);
+
+    input_state.importing_blueprint = kb_input.just_pressed(KeyCode::KeyI);
+
+    input_state.clearing_hand = kb_input.just_pressed(KeyCode::KeyQ);
+
+    let mut move_delta = direction.normalize_or_zero() * PLAYER_SPEED * time.delta_seconds();
+
+    // belts carry the player along at the speed of their items
+    let pos = player.translation.truncate();
+    let cell = (
+        (pos.x / 32.0 + 0.5).floor() as i32,
+        (pos.y / 32.0 + 0.5).floor() as i32,
+    );
+    if let Some(tile) = game_world.tiles.get(&cell) {
+        if let Some(speed) = belt_speed(tile.tile_type) {
+            let dir = match tile.rotation {
+                0 => vec2(1.0, 0.0),
+                1 => vec2(0.0, 1.0),
+                2 => vec2(-1.0, 0.0),
+                3 => vec2(0.0, -1.0),
+                _ => unreachable!(),
+            };
+            move_delta += dir * speed / fixed_time.timestep().as_secs_f32() * time.delta_seconds();
+        }
     }
 
-    let move_delta = direction.normalize_or_zero() * PLAYER_SPEED * time.delta_seconds();
-    player.translation += move_delta.extend(0.);
+    // each axis is moved separately to slide along edges,
+    // and a player stuck inside a tile can always walk out
+    let mut new_pos = pos;
+    for step in [vec2(move_delta.x, 0.0), vec2(0.0, move_delta.y)] {
+        if !collides(&game_world, new_pos + step) || collides(&game_world, new_pos) {
+            new_pos += step;
+        }
+    }
+    player.translation = new_pos.extend(player.translation.z);
 }
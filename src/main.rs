@@ -1,10 +1,30 @@
 mod actions;
+mod blueprints;
+mod combat;
+mod crafting;
+mod deconstruction;
+mod enemies;
+mod health;
+mod history;
 mod input;
+mod placement;
+mod rails;
+mod robots;
+mod selection;
+mod settings;
 mod sprites;
+mod stats;
+mod turrets;
 mod ui;
 mod updates;
-
-use bevy::{math::vec3, prelude::*, sprite::MaterialMesh2dBundle, utils::HashMap};
+mod upgrades;
+
+use bevy::{
+    math::{vec2, vec3},
+    prelude::*,
+    sprite::MaterialMesh2dBundle,
+    utils::{HashMap, HashSet},
+};
 use bevy_prng::ChaCha8Rng;
 use bevy_rand::{plugin::EntropyPlugin, prelude::GlobalEntropy};
 use rand::Rng;
@@ -29,7 +49,35 @@ fn main() {
         .add_plugins(EntropyPlugin::<ChaCha8Rng>::default())
         .insert_resource(InputState::default())
         .insert_resource(GameWorld::default())
-        .add_systems(Startup, setup_scene)
+        .insert_resource(rails::RailNetwork::default())
+        .insert_resource(rails::ScheduleEditor::default())
+        .insert_resource(enemies::AttackWaves::default())
+        .insert_resource(health::RepairPack::default())
+        .insert_resource(combat::WeaponState::default())
+        .insert_resource(stats::ProductionStats::default())
+        .insert_resource(stats::StatsPanel::default())
+        .insert_resource(blueprints::BlueprintState::default())
+        .insert_resource(history::BuildHistory::default())
+        .insert_resource(actions::PlayerConstruction::default())
+        .insert_resource(selection::AreaSelection::default())
+        .insert_resource(deconstruction::DeconstructionQueue::default())
+        .insert_resource(settings::CopiedSettings::default())
+        .insert_resource(crafting::CraftingQueue::default())
+        .insert_resource(ui::SelectedCategory::default())
+        .insert_resource(ui::DraggedSlot::default())
+        .add_event::<health::DamageEvent>()
+        .add_event::<selection::AreaSelected>()
+        .add_event::<actions::TileRotated>()
+        .add_systems(
+            Startup,
+            (
+                setup_scene,
+                enemies::spawn_enemy_spawners,
+                combat::setup_projectile_assets,
+                crafting::setup_crafting_hud,
+                rails::setup_schedule_hud,
+            ),
+        )
         .add_systems(Update, (input::move_player, input::update_camera).chain())
         .add_systems(
             Update,
@@ -39,6 +87,44 @@ fn main() {
                 updates::update_preview_tile,
                 updates::update_rotating_tiles,
                 ui::hanle_player_inventory_ui_events,
+                ui::update_crafting_tabs,
+                ui::update_inventory_ui,
+                ui::update_tooltip,
+                ui::handle_inventory_slot_moves,
+                rails::handle_rolling_stock_actions.after(actions::handle_player_actions),
+                rails::update_train_sprites,
+                robots::handle_logistic_actions,
+                turrets::handle_turret_actions,
+                combat::handle_shooting,
+                stats::handle_production_stats_ui,
+                health::repair_buildings,
+                health::apply_damage,
+                health::update_remnants,
+                health::draw_health_bars,
+            ),
+        )
+        .add_systems(
+            Update,
+            (
+                selection::select_area,
+                blueprints::handle_blueprint_actions,
+                deconstruction::handle_deconstruction_planner,
+                deconstruction::deconstruct_marked_tiles,
+                upgrades::handle_upgrade_planner,
+                actions::handle_rotation,
+                actions::handle_pipette,
+                actions::mine_by_hand,
+                actions::draw_mining_progress,
+                settings::copy_paste_settings,
+                actions::apply_tile_rotations.after(actions::handle_rotation),
+                history::handle_undo_redo,
+                actions::build_nearby_ghosts,
+                actions::spread_spilled_items,
+                crafting::update_crafting,
+                crafting::update_crafting_hud,
+                crafting::handle_crafting_queue_clicks,
+                rails::edit_train_schedules,
+                rails::update_schedule_hud.after(rails::edit_train_schedules),
             ),
         )
         .add_systems(
@@ -48,6 +134,14 @@ fn main() {
                 updates::update_miners,
                 updates::update_movers,
                 updates::update_item_processors,
+                rails::update_trains,
+                robots::dispatch_robots,
+                robots::update_robots,
+                enemies::schedule_attack_waves,
+                enemies::update_enemies,
+                turrets::update_turrets,
+                combat::update_projectiles,
+                stats::update_production_stats,
             ),
         )
         .run();
@@ -57,25 +151,49 @@ fn main() {
 struct ResourceType {
     texture_name: &'static str,
     item_to_produce: ItemType,
+    /// Time it takes to mine one item by hand.
+    mining_time_ms: u32,
 }
 
 impl ResourceType {
-    const fn new(texture_name: &'static str, item_to_produce: ItemType) -> Self {
+    const fn new(
+        texture_name: &'static str,
+        item_to_produce: ItemType,
+        mining_time_ms: u32,
+    ) -> Self {
         Self {
             texture_name,
             item_to_produce,
+            mining_time_ms,
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 struct ItemType {
     texture_name: &'static str,
+    /// Human-readable name shown in the UI.
+    name: &'static str,
+    description: &'static str,
+    /// Number of items that fit into one inventory slot.
+    stack_size: u32,
 }
 
 impl ItemType {
-    const fn new(texture_name: &'static str) -> Self {
-        Self { texture_name }
+    const fn new(texture_name: &'static str, name: &'static str, stack_size: u32) -> Self {
+        Self {
+            texture_name,
+            name,
+            description: "",
+            stack_size,
+        }
+    }
+
+    const fn with_description(self, description: &'static str) -> Self {
+        Self {
+            description,
+            ..self
+        }
     }
 }
 
@@ -84,6 +202,11 @@ struct TileType {
     texture_name: &'static str,
     rotating_texture_name: Option<&'static str>,
     item_to_drop: ItemType,
+    max_health: u32,
+    /// The tile can only be built on a resource, like a miner.
+    requires_resource: bool,
+    /// The player can walk over the tile, like over belts and rails.
+    walkable: bool,
 }
 
 impl TileType {
@@ -92,6 +215,9 @@ impl TileType {
             texture_name,
             rotating_texture_name: None,
             item_to_drop,
+            max_health: 100,
+            requires_resource: false,
+            walkable: false,
         }
     }
 
@@ -101,46 +227,210 @@ impl TileType {
             ..self
         }
     }
+
+    const fn with_health(self, max_health: u32) -> Self {
+        Self { max_health, ..self }
+    }
+
+    const fn on_resource(self) -> Self {
+        Self {
+            requires_resource: true,
+            ..self
+        }
+    }
+
+    const fn walkable(self) -> Self {
+        Self {
+            walkable: true,
+            ..self
+        }
+    }
 }
 
 #[derive(Resource, Default)]
 struct GameWorld {
     tiles: HashMap<(i32, i32), PlacedTile>,
+    ghosts: HashMap<(i32, i32), Entity>,
+    /// Cells that can't be built on, like the ones of enemy spawners.
+    reserved: HashSet<(i32, i32)>,
 }
 
 static RESOURCE_TYPES: [ResourceType; 3] = [
-    ResourceType::new("coal", items::COAL),
-    ResourceType::new("iron_ore", items::IRON_ORE),
-    ResourceType::new("copper_ore", items::COPPER_ORE),
+    ResourceType::new("coal", items::COAL, 800),
+    ResourceType::new("iron_ore", items::IRON_ORE, 1000),
+    ResourceType::new("copper_ore", items::COPPER_ORE, 1000),
 ];
 
 mod items {
     use super::ItemType;
 
-    pub static COAL: ItemType = ItemType::new("coal");
-    pub static IRON_ORE: ItemType = ItemType::new("iron_ore");
-    pub static COPPER_ORE: ItemType = ItemType::new("copper_ore");
-    pub static IRON_SHEET: ItemType = ItemType::new("iron_sheet");
-    pub static COPPER_SHEET: ItemType = ItemType::new("copper_sheet");
-    pub static BELT: ItemType = ItemType::new("belt");
-    pub static MINER: ItemType = ItemType::new("miner");
-    pub static INSERTER: ItemType = ItemType::new("inserter");
-    pub static FURNACE: ItemType = ItemType::new("furnace");
+    pub static COAL: ItemType =
+        ItemType::new("coal", "Coal", 50).with_description("Mined from coal deposits.");
+    pub static IRON_ORE: ItemType = ItemType::new("iron_ore", "Iron ore", 50)
+        .with_description("Smelted into iron sheets in a furnace.");
+    pub static COPPER_ORE: ItemType = ItemType::new("copper_ore", "Copper ore", 50)
+        .with_description("Smelted into copper sheets in a furnace.");
+    pub static IRON_SHEET: ItemType = ItemType::new("iron_sheet", "Iron sheet", 100)
+        .with_description("Basic material of most buildings.");
+    pub static COPPER_SHEET: ItemType = ItemType::new("copper_sheet", "Copper sheet", 100)
+        .with_description("Used for anything with wiring.");
+    pub static BELT: ItemType = ItemType::new("belt", "Belt", 100)
+        .with_description("Moves items in the direction it faces.");
+    pub static MINER: ItemType =
+        ItemType::new("miner", "Miner", 50).with_description("Mines the resource it is built on.");
+    pub static INSERTER: ItemType = ItemType::new("inserter", "Inserter", 50)
+        .with_description("Moves items from behind it to the front.");
+    pub static FURNACE: ItemType =
+        ItemType::new("furnace", "Furnace", 50).with_description("Smelts ore into sheets.");
+    pub static RAIL: ItemType =
+        ItemType::new("rail", "Rail", 100).with_description("Straight track for trains.");
+    pub static CURVED_RAIL: ItemType = ItemType::new("curved_rail", "Curved rail", 50)
+        .with_description("Turns the track of trains.");
+    pub static TRAIN_STOP: ItemType = ItemType::new("train_stop", "Train stop", 10)
+        .with_description("Trains stop here to load and unload.");
+    pub static LOCOMOTIVE: ItemType = ItemType::new("locomotive", "Locomotive", 5)
+        .with_description("Pulls the cargo wagons behind it.");
+    pub static CARGO_WAGON: ItemType = ItemType::new("cargo_wagon", "Cargo wagon", 5)
+        .with_description("Carries items on the rails.");
+    pub static ROBOPORT: ItemType = ItemType::new("roboport", "Roboport", 10)
+        .with_description("Home of logistic and construction robots.");
+    pub static LOGISTIC_ROBOT: ItemType = ItemType::new("logistic_robot", "Logistic robot", 50)
+        .with_description("Carries items between logistic chests.");
+    pub static CONSTRUCTION_ROBOT: ItemType =
+        ItemType::new("construction_robot", "Construction robot", 50)
+            .with_description("Builds ghosts in range of a roboport.");
+    pub static PROVIDER_CHEST: ItemType = ItemType::new("provider_chest", "Provider chest", 50)
+        .with_description("Offers its items to logistic robots.");
+    pub static REQUESTER_CHEST: ItemType = ItemType::new("requester_chest", "Requester chest", 50)
+        .with_description("Asks logistic robots for an item.");
+    pub static STORAGE_CHEST: ItemType = ItemType::new("storage_chest", "Storage chest", 50)
+        .with_description("Keeps items delivered by robots.");
+    pub static REPAIR_PACK: ItemType = ItemType::new("repair_pack", "Repair pack", 100)
+        .with_description("Restores the health of damaged buildings.");
+    pub static AMMO: ItemType = ItemType::new("ammo", "Ammo", 200)
+        .with_description("Loaded into gun turrets and the pistol.");
+    pub static GUN_TURRET: ItemType = ItemType::new("gun_turret", "Gun turret", 10)
+        .with_description("Shoots enemies in range while it has ammo.");
+    pub static WALL: ItemType = ItemType::new("wall", "Wall", 100)
+        .with_description("Blocks enemies until it is destroyed.");
+    pub static PISTOL: ItemType =
+        ItemType::new("pistol", "Pistol", 5).with_description("Shoots towards the mouse.");
+    pub static FAST_BELT: ItemType = ItemType::new("fast_belt", "Fast belt", 100)
+        .with_description("Moves items twice as fast as a belt.");
+    pub static FAST_INSERTER: ItemType = ItemType::new("fast_inserter", "Fast inserter", 50)
+        .with_description("Moves items twice as fast as an inserter.");
+    pub static IRON_GEAR: ItemType = ItemType::new("iron_gear", "Iron gear", 100)
+        .with_description("Intermediate part of machines.");
+
+    static ALL: [ItemType; 28] = [
+        COAL,
+        IRON_ORE,
+        COPPER_ORE,
+        IRON_SHEET,
+        COPPER_SHEET,
+        BELT,
+        MINER,
+        INSERTER,
+        FURNACE,
+        RAIL,
+        CURVED_RAIL,
+        TRAIN_STOP,
+        LOCOMOTIVE,
+        CARGO_WAGON,
+        ROBOPORT,
+        LOGISTIC_ROBOT,
+        CONSTRUCTION_ROBOT,
+        PROVIDER_CHEST,
+        REQUESTER_CHEST,
+        STORAGE_CHEST,
+        REPAIR_PACK,
+        AMMO,
+        GUN_TURRET,
+        WALL,
+        PISTOL,
+        FAST_BELT,
+        FAST_INSERTER,
+        IRON_GEAR,
+    ];
+
+    pub fn by_name(name: &str) -> Option<ItemType> {
+        ALL.iter().copied().find(|t| t.texture_name == name)
+    }
+
+    /// Position of the item type when sorting the inventory.
+    pub fn sort_index(item_type: ItemType) -> usize {
+        ALL.iter()
+            .position(|t| *t == item_type)
+            .unwrap_or(ALL.len())
+    }
 }
 
 mod tiles {
-    use crate::items;
+    use crate::{items, ItemType};
 
     use super::TileType;
 
-    pub static BELT: TileType = TileType::new("belt", items::BELT);
-    pub static MINER: TileType = TileType::new("miner", items::MINER);
+    pub static BELT: TileType = TileType::new("belt", items::BELT).walkable();
+    pub static MINER: TileType = TileType::new("miner", items::MINER).on_resource();
     pub static INSERTER: TileType =
         TileType::new("inserter_base", items::INSERTER).with_rotating_part("inserter_hand");
     pub static FURNACE: TileType = TileType::new("furnace", items::FURNACE);
+    pub static RAIL: TileType = TileType::new("rail", items::RAIL).walkable();
+    pub static CURVED_RAIL: TileType = TileType::new("curved_rail", items::CURVED_RAIL).walkable();
+    pub static TRAIN_STOP: TileType = TileType::new("train_stop", items::TRAIN_STOP);
+    pub static ROBOPORT: TileType = TileType::new("roboport", items::ROBOPORT);
+    pub static PROVIDER_CHEST: TileType = TileType::new("provider_chest", items::PROVIDER_CHEST);
+    pub static REQUESTER_CHEST: TileType = TileType::new("requester_chest", items::REQUESTER_CHEST);
+    pub static STORAGE_CHEST: TileType = TileType::new("storage_chest", items::STORAGE_CHEST);
+    pub static GUN_TURRET: TileType =
+        TileType::new("gun_turret", items::GUN_TURRET).with_health(200);
+    pub static WALL: TileType = TileType::new("wall", items::WALL).with_health(500);
+    pub static FAST_BELT: TileType = TileType::new("fast_belt", items::FAST_BELT).walkable();
+    pub static FAST_INSERTER: TileType = TileType::new("fast_inserter_base", items::FAST_INSERTER)
+        .with_rotating_part("fast_inserter_hand");
+
+    static ALL: [TileType; 15] = [
+        BELT,
+        MINER,
+        INSERTER,
+        FURNACE,
+        RAIL,
+        CURVED_RAIL,
+        TRAIN_STOP,
+        ROBOPORT,
+        PROVIDER_CHEST,
+        REQUESTER_CHEST,
+        STORAGE_CHEST,
+        GUN_TURRET,
+        WALL,
+        FAST_BELT,
+        FAST_INSERTER,
+    ];
+
+    /// Pairs of tiles and the better tier they can be upgraded to.
+    static UPGRADES: [(TileType, TileType); 2] = [(BELT, FAST_BELT), (INSERTER, FAST_INSERTER)];
+
+    /// Returns the tile that gets placed when building with the given item.
+    pub fn for_item(item: ItemType) -> Option<TileType> {
+        ALL.iter().copied().find(|t| t.item_to_drop == item)
+    }
+
+    pub fn by_name(name: &str) -> Option<TileType> {
+        ALL.iter().copied().find(|t| t.texture_name == name)
+    }
+
+    pub fn upgrade_of(tile_type: TileType) -> Option<TileType> {
+        UPGRADES
+            .iter()
+            .find(|(from, _)| *from == tile_type)
+            .map(|(_, to)| *to)
+    }
 }
 
 const PLAYER_SPEED: f32 = 200.;
+const PLAYER_RADIUS: f32 = 5.;
+/// Number of slots in the player inventory.
+const INVENTORY_SLOTS: usize = 90;
 
 #[derive(Resource, Default)]
 struct InputState {
@@ -151,10 +441,55 @@ struct InputState {
     picking_items_timer: Option<Timer>,
     deleting_tile: bool,
     deleting_tile_timer: Option<Timer>,
+    mining: Option<actions::HandMining>,
     rotation: u8,
     item_in_hand: Option<ItemType>,
     inventory_ui: Option<Entity>,
     toggling_inventory_visible: bool,
+    placing_ghosts: bool,
+    setting_request: bool,
+    toggling_schedule_editor: bool,
+    removing_schedule_entry: bool,
+    shooting: bool,
+    toggling_stats_visible: bool,
+    clearing_hand: bool,
+    /// Tool used to select an area of tiles instead of placing the item in hand.
+    selection_tool: Option<SelectionTool>,
+    toggling_blueprint_tool: bool,
+    toggling_deconstruction_tool: bool,
+    toggling_upgrade_tool: bool,
+    exporting_blueprint: bool,
+    importing_blueprint: bool,
+    undoing: bool,
+    redoing: bool,
+    rotating: bool,
+    copying_settings: bool,
+    pasting_settings: bool,
+}
+
+/// The world position under the mouse cursor, if it is inside the window.
+fn cursor_world_pos(
+    window: &Window,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+) -> Option<Vec2> {
+    window
+        .cursor_position()
+        .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor))
+        .map(|ray| ray.origin.truncate())
+}
+
+/// The cell under the mouse cursor, if it is inside the window.
+fn cursor_cell(
+    window: &Window,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+) -> Option<(i32, i32)> {
+    let pos = cursor_world_pos(window, camera, camera_transform)?;
+    Some((
+        (pos.x / 32.0 + 0.5).floor() as i32,
+        (pos.y / 32.0 + 0.5).floor() as i32,
+    ))
 }
 
 #[derive(Component)]
@@ -169,9 +504,14 @@ struct TileRotation {
 enum Layer {
     Background,
     Resource,
+    Remnants,
     Tile,
     Item,
+    Vehicle,
+    Enemy,
     Player,
+    Projectile,
+    Robot,
 }
 
 impl Layer {
@@ -179,48 +519,149 @@ impl Layer {
         match self {
             Layer::Background => 0.0,
             Layer::Resource => 0.1,
+            Layer::Remnants => 0.15,
             Layer::Tile => 0.2,
             Layer::Item => 0.3,
+            Layer::Vehicle => 0.35,
+            Layer::Enemy => 0.38,
             Layer::Player => 0.4,
+            Layer::Projectile => 0.45,
+            Layer::Robot => 0.5,
         }
     }
 }
 
 #[derive(Component)]
 struct Player {
-    inventory: Vec<(ItemType, usize)>,
+    /// Fixed slots, each empty or holding a stack of up to `stack_size` items of one type.
+    inventory: Vec<Option<(ItemType, usize)>>,
+    weapon: Option<ItemType>,
 }
 
 impl Player {
-    pub fn has_item_in_inventory(&mut self, item_type: ItemType) -> bool {
+    fn new(items: &[(ItemType, usize)], weapon: Option<ItemType>) -> Self {
+        let mut player = Self {
+            inventory: vec![None; INVENTORY_SLOTS],
+            weapon,
+        };
+        for &(item_type, count) in items {
+            player.add_items(item_type, count);
+        }
+        player
+    }
+
+    pub fn has_item_in_inventory(&self, item_type: ItemType) -> bool {
         self.inventory
             .iter()
+            .flatten()
             .any(|(t, c)| *t == item_type && *c > 0)
     }
 
+    /// Takes one item out of the last stack of its type, leaving the slot empty if it runs out.
     pub fn decrement_inventory(&mut self, item_type: ItemType) -> bool {
-        let res = if let Some((_, c)) = self
+        let Some(slot) = self
             .inventory
             .iter_mut()
-            .find(|(t, c)| *t == item_type && *c > 0)
-        {
-            *c -= 1;
-            true
-        } else {
-            false
+            .rev()
+            .find(|s| s.is_some_and(|(t, _)| t == item_type))
+        else {
+            return false;
         };
-        self.inventory.retain(|(_, c)| *c > 0);
-        res
+        if let Some((_, c)) = slot {
+            *c -= 1;
+            if *c == 0 {
+                *slot = None;
+            }
+        }
+        true
     }
 
+    /// Returns false if the inventory is full, in which case the item has to go elsewhere.
+    #[must_use = "the item is lost if the inventory is full"]
     pub fn increment_inventory(&mut self, item_type: ItemType) -> bool {
-        if let Some((_, c)) = self.inventory.iter_mut().find(|(t, _)| *t == item_type) {
-            *c += 1;
-            true
-        } else {
-            self.inventory.push((item_type, 1));
-            true
+        self.add_items(item_type, 1) == 0
+    }
+
+    /// Fills up existing stacks of the type first, then empty slots.
+    /// Returns the number of items that didn't fit.
+    pub fn add_items(&mut self, item_type: ItemType, mut count: usize) -> usize {
+        let stack_size = item_type.stack_size as usize;
+        for (t, c) in self.inventory.iter_mut().flatten() {
+            if *t == item_type && *c < stack_size {
+                let moved = count.min(stack_size - *c);
+                *c += moved;
+                count -= moved;
+            }
+        }
+        for slot in self.inventory.iter_mut().filter(|s| s.is_none()) {
+            if count == 0 {
+                break;
+            }
+            let moved = count.min(stack_size);
+            *slot = Some((item_type, moved));
+            count -= moved;
+        }
+        count
+    }
+
+    /// Moves the stack in one slot onto another. Stacks of the same type are merged
+    /// as far as the stack size allows, otherwise the two slots are swapped.
+    pub fn move_stack(&mut self, from: usize, to: usize) {
+        if from == to || from >= self.inventory.len() || to >= self.inventory.len() {
+            return;
         }
+        match (self.inventory[from], self.inventory[to]) {
+            (Some((t, c)), Some((target_type, target_count))) if t == target_type => {
+                let moved = c.min((t.stack_size as usize).saturating_sub(target_count));
+                self.inventory[to] = Some((t, target_count + moved));
+                self.inventory[from] = (c > moved).then_some((t, c - moved));
+            }
+            _ => self.inventory.swap(from, to),
+        }
+    }
+
+    /// Moves half of a stack into the first empty slot.
+    pub fn split_stack(&mut self, idx: usize) {
+        let Some(Some((t, c))) = self.inventory.get(idx).copied() else {
+            return;
+        };
+        let Some(empty) = self.inventory.iter().position(|s| s.is_none()) else {
+            return;
+        };
+        if c < 2 {
+            return;
+        }
+        self.inventory[idx] = Some((t, c - c / 2));
+        self.inventory[empty] = Some((t, c / 2));
+    }
+
+    /// Merges stacks of the same type and orders them by item type, empty slots last.
+    pub fn sort_inventory(&mut self) {
+        let mut totals: Vec<(ItemType, usize)> = Vec::new();
+        for (t, c) in self.inventory.iter().flatten() {
+            match totals.iter_mut().find(|(tt, _)| tt == t) {
+                Some((_, total)) => *total += c,
+                None => totals.push((*t, *c)),
+            }
+        }
+        totals.sort_by_key(|(t, _)| items::sort_index(*t));
+        self.inventory.fill(None);
+        for (t, c) in totals {
+            // merging never needs more slots than before
+            self.add_items(t, c);
+        }
+    }
+}
+
+#[derive(Component)]
+struct Health {
+    current: f32,
+    max: f32,
+}
+
+impl Health {
+    fn new(max: f32) -> Self {
+        Self { current: max, max }
     }
 }
 
@@ -229,7 +670,7 @@ struct DroppedItem {
     item_type: ItemType,
 }
 
-#[derive(Component, Clone)]
+#[derive(Component, Debug, PartialEq, Eq, Clone)]
 struct PlacedTile {
     tile_type: TileType,
     rotation: u8,
@@ -250,6 +691,79 @@ struct ItemProcessor {
     output: Option<(ItemType, usize)>,
 }
 
+#[derive(Component)]
+struct ItemContainer {
+    items: Vec<(ItemType, usize)>,
+    capacity: usize,
+    /// Whether inserters are allowed to put items in or take items out.
+    accessible: bool,
+    /// Whether inserters are allowed to take items out, turrets only accept ammo.
+    extractable: bool,
+    /// Only items of this type can be inserted, if set.
+    filter: Option<ItemType>,
+}
+
+impl ItemContainer {
+    fn new(capacity: usize) -> Self {
+        Self {
+            items: Vec::new(),
+            capacity,
+            accessible: true,
+            extractable: true,
+            filter: None,
+        }
+    }
+
+    fn count(&self, item_type: ItemType) -> usize {
+        self.items
+            .iter()
+            .filter(|(t, _)| *t == item_type)
+            .map(|(_, c)| c)
+            .sum()
+    }
+
+    fn total(&self) -> usize {
+        self.items.iter().map(|(_, c)| c).sum()
+    }
+
+    fn is_full(&self) -> bool {
+        self.total() >= self.capacity
+    }
+
+    fn insert(&mut self, item_type: ItemType) -> bool {
+        if self.is_full() || self.filter.is_some_and(|f| f != item_type) {
+            return false;
+        }
+        if let Some((_, c)) = self.items.iter_mut().find(|(t, _)| *t == item_type) {
+            *c += 1;
+        } else {
+            self.items.push((item_type, 1));
+        }
+        true
+    }
+
+    fn remove(&mut self, item_type: ItemType) -> bool {
+        let res = if let Some((_, c)) = self
+            .items
+            .iter_mut()
+            .find(|(t, c)| *t == item_type && *c > 0)
+        {
+            *c -= 1;
+            true
+        } else {
+            false
+        };
+        self.items.retain(|(_, c)| *c > 0);
+        res
+    }
+
+    fn take_any(&mut self) -> Option<ItemType> {
+        let item_type = self.items.first()?.0;
+        self.remove(item_type);
+        Some(item_type)
+    }
+}
+
 #[derive(Component)]
 struct ItemMover {
     item: Option<ItemType>,
@@ -265,6 +779,27 @@ struct ResourceTile {
 #[derive(Component)]
 struct PreviewTile;
 
+#[derive(Component)]
+struct GhostTile {
+    tile: PlacedTile,
+    /// Whether a construction robot is on its way to build this ghost.
+    claimed: bool,
+    settings: Option<TileSettings>,
+}
+
+/// Configuration of a placed tile that is kept when copying it, e.g. in blueprints.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum TileSettings {
+    Request(ItemType),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum SelectionTool {
+    Blueprint,
+    Deconstruction,
+    Upgrade,
+}
+
 fn setup_scene(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
@@ -342,16 +877,36 @@ fn setup_scene(
     }
 
     commands.spawn((
-        Player {
-            inventory: vec![
+        Player::new(
+            &[
                 (items::BELT, 100),
                 (items::INSERTER, 50),
+                (items::FAST_BELT, 50),
+                (items::FAST_INSERTER, 20),
                 (items::FURNACE, 10),
                 (items::MINER, 20),
+                (items::RAIL, 100),
+                (items::CURVED_RAIL, 20),
+                (items::TRAIN_STOP, 4),
+                (items::LOCOMOTIVE, 2),
+                (items::CARGO_WAGON, 4),
+                (items::ROBOPORT, 2),
+                (items::LOGISTIC_ROBOT, 10),
+                (items::CONSTRUCTION_ROBOT, 10),
+                (items::PROVIDER_CHEST, 10),
+                (items::REQUESTER_CHEST, 10),
+                (items::STORAGE_CHEST, 10),
+                (items::REPAIR_PACK, 10),
+                (items::AMMO, 50),
+                (items::GUN_TURRET, 4),
+                (items::WALL, 50),
             ],
-        },
+            Some(items::PISTOL),
+        ),
+        actions::PlayerReach::default(),
+        Health::new(100.0),
         MaterialMesh2dBundle {
-            mesh: meshes.add(Circle::new(5.)).into(),
+            mesh: meshes.add(Circle::new(PLAYER_RADIUS)).into(),
             material: materials.add(Color::srgb(1.0, 1.0, 1.0)),
             transform: Transform {
                 translation: vec3(0., 0., Layer::Player.depth()),
@@ -362,6 +917,13 @@ fn setup_scene(
     ));
 }
 
+/// The cell a dropped item lies on. Item sprites are offset so the icon is drawn
+/// at the center of the transform's top left quarter.
+fn item_cell(transform: &Transform) -> (i32, i32) {
+    let cell = ((transform.translation.truncate() + vec2(-8.0, 8.0)) / 32.0 + 0.5).floor();
+    (cell.x as i32, cell.y as i32)
+}
+
 fn calc_rotating_tile_transform(tile: &PlacedTile, anchor: Vec2, angle: f32) -> Transform {
     let mut transform = Transform::from_scale(Vec3::splat(1.0))
         .with_rotation(Quat::from_rotation_z(PI / 2.0 * tile.rotation as f32))
@@ -374,3 +936,84 @@ fn calc_rotating_tile_transform(tile: &PlacedTile, anchor: Vec2, angle: f32) ->
     transform = transform.mul_transform(Transform::from_translation(-anchor.extend(0.0) * 32.0));
     transform
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_items_fills_partial_stacks_before_empty_slots() {
+        let mut player = Player::new(&[], None);
+        player.inventory[0] = Some((items::COAL, 30));
+        player.inventory[2] = Some((items::COAL, 45));
+
+        assert_eq!(player.add_items(items::COAL, 80), 0);
+
+        assert_eq!(player.inventory[0], Some((items::COAL, 50)));
+        assert_eq!(player.inventory[1], Some((items::COAL, 50)));
+        assert_eq!(player.inventory[2], Some((items::COAL, 50)));
+        assert_eq!(player.inventory[3], Some((items::COAL, 5)));
+        assert_eq!(player.inventory[4], None);
+    }
+
+    #[test]
+    fn add_items_returns_overflow_when_full() {
+        let mut player = Player::new(&[(items::WALL, 100 * INVENTORY_SLOTS - 3)], None);
+
+        assert_eq!(player.add_items(items::WALL, 10), 7);
+        assert!(!player.increment_inventory(items::WALL));
+        assert!(!player.increment_inventory(items::COAL));
+        assert!(player
+            .inventory
+            .iter()
+            .all(|s| *s == Some((items::WALL, 100))));
+    }
+
+    #[test]
+    fn move_stack_merges_up_to_the_target_stack_size() {
+        let mut player = Player::new(&[], None);
+        player.inventory[0] = Some((items::LOCOMOTIVE, 4));
+        player.inventory[1] = Some((items::LOCOMOTIVE, 3));
+
+        player.move_stack(0, 1);
+
+        assert_eq!(player.inventory[0], Some((items::LOCOMOTIVE, 2)));
+        assert_eq!(player.inventory[1], Some((items::LOCOMOTIVE, 5)));
+
+        player.inventory[2] = Some((items::COAL, 7));
+        player.move_stack(2, 0);
+
+        assert_eq!(player.inventory[0], Some((items::COAL, 7)));
+        assert_eq!(player.inventory[2], Some((items::LOCOMOTIVE, 2)));
+    }
+
+    #[test]
+    fn split_stack_needs_an_empty_slot() {
+        let mut player = Player::new(&[(items::COAL, 50 * INVENTORY_SLOTS)], None);
+        player.inventory[0] = Some((items::COAL, 9));
+
+        player.split_stack(0);
+        assert_eq!(player.inventory[0], Some((items::COAL, 9)));
+
+        player.inventory[1] = None;
+        player.split_stack(0);
+        assert_eq!(player.inventory[0], Some((items::COAL, 5)));
+        assert_eq!(player.inventory[1], Some((items::COAL, 4)));
+    }
+
+    #[test]
+    fn sort_inventory_keeps_the_total_count() {
+        let mut player = Player::new(&[], None);
+        player.inventory[5] = Some((items::IRON_SHEET, 70));
+        player.inventory[1] = Some((items::COAL, 20));
+        player.inventory[8] = Some((items::IRON_SHEET, 60));
+        player.inventory[3] = Some((items::COAL, 20));
+
+        player.sort_inventory();
+
+        assert_eq!(player.inventory[0], Some((items::COAL, 40)));
+        assert_eq!(player.inventory[1], Some((items::IRON_SHEET, 100)));
+        assert_eq!(player.inventory[2], Some((items::IRON_SHEET, 30)));
+        assert!(player.inventory[3..].iter().all(Option::is_none));
+    }
+}
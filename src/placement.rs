@@ -0,0 +1,41 @@
+use bevy::{ecs::system::SystemParam, math::vec2, prelude::*};
+
+use crate::{
+    enemies::{Enemy, ENEMY_RADIUS},
+    item_cell, DroppedItem, GameWorld, Player, ResourceTile, TileType, PLAYER_RADIUS,
+};
+
+/// Checks the placement rules of tile types against the cells they are built on.
+/// The map has no water yet, so the only terrain rule is `TileType::requires_resource`;
+/// a rule for tiles that must be built on water is left out until there is water.
+#[derive(SystemParam)]
+pub struct Placement<'w, 's> {
+    q_resource_tiles: Query<'w, 's, &'static ResourceTile>,
+    q_player: Query<'w, 's, &'static Transform, With<Player>>,
+    q_enemies: Query<'w, 's, &'static Transform, With<Enemy>>,
+    q_items: Query<'w, 's, &'static Transform, With<DroppedItem>>,
+}
+
+impl Placement<'_, '_> {
+    /// Returns whether a tile of this type can be built at the cell: it must be empty and not reserved,
+    /// on a resource if the tile needs one, and not covered by the player, an enemy or an item on the ground.
+    pub fn is_valid(&self, game_world: &GameWorld, tile_type: TileType, x: i32, y: i32) -> bool {
+        if game_world.tiles.contains_key(&(x, y)) || game_world.reserved.contains(&(x, y)) {
+            return false;
+        }
+        if tile_type.requires_resource
+            && !self.q_resource_tiles.iter().any(|t| t.x == x && t.y == y)
+        {
+            return false;
+        }
+        let center = vec2(x as f32, y as f32) * 32.0;
+        let overlaps = |tr: &Transform, radius: f32| {
+            let d = (tr.translation.truncate() - center).abs();
+            d.x < 16.0 + radius && d.y < 16.0 + radius
+        };
+        let unit_blocks = self.q_player.iter().any(|tr| overlaps(tr, PLAYER_RADIUS))
+            || self.q_enemies.iter().any(|tr| overlaps(tr, ENEMY_RADIUS));
+        let item_blocks = self.q_items.iter().any(|tr| item_cell(tr) == (x, y));
+        !unit_blocks && !item_blocks
+    }
+}
@@ -0,0 +1,699 @@
+use std::{collections::VecDeque, f32::consts::PI};
+
+use bevy::{
+    math::{vec2, vec3},
+    prelude::*,
+    utils::HashMap,
+    window::PrimaryWindow,
+};
+
+use crate::{
+    actions, cursor_cell, items, tiles, ui, GameWorld, InputState, ItemContainer, ItemType, Layer,
+    PlacedTile, Player,
+};
+
+const TRAIN_SPEED: f32 = 4.0;
+const WAGON_CAPACITY: usize = 100;
+
+#[derive(Resource, Default)]
+pub struct RailNetwork {
+    occupied: HashMap<(i32, i32), Entity>,
+    next_station_id: u32,
+}
+
+impl RailNetwork {
+    pub fn is_occupied(&self, x: i32, y: i32) -> bool {
+        self.occupied.contains_key(&(x, y))
+    }
+
+    pub fn next_station_name(&mut self) -> String {
+        let id = self.next_station_id;
+        self.next_station_id += 1;
+
+        let letter = (b'A' + (id % 26) as u8) as char;
+        if id < 26 {
+            letter.to_string()
+        } else {
+            format!("{}{}", letter, id / 26)
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct TrainStop {
+    pub name: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WaitCondition {
+    Full,
+    Empty,
+}
+
+impl WaitCondition {
+    fn label(self) -> &'static str {
+        match self {
+            WaitCondition::Full => "full",
+            WaitCondition::Empty => "empty",
+        }
+    }
+}
+
+pub struct ScheduleEntry {
+    pub station: String,
+    pub wait: WaitCondition,
+}
+
+enum TrainState {
+    Moving,
+    Waiting,
+}
+
+#[derive(Component)]
+pub struct Train {
+    /// The cars of the train, from front to back.
+    cars: Vec<Entity>,
+    /// The cells occupied by the cars, in the same order as `cars`.
+    trail: VecDeque<(i32, i32)>,
+    /// The direction in which the front car leaves its cell.
+    heading: u8,
+    path: VecDeque<(i32, i32)>,
+    progress: f32,
+    pub schedule: Vec<ScheduleEntry>,
+    current_stop: usize,
+    state: TrainState,
+}
+
+#[derive(Component)]
+pub struct TrainCar {
+    item_to_drop: ItemType,
+}
+
+/// The cargo is stored in an `ItemContainer` which is only accessible while the train is stopped.
+#[derive(Component)]
+pub struct CargoWagon;
+
+fn step((x, y): (i32, i32), dir: u8) -> (i32, i32) {
+    match dir {
+        0 => (x + 1, y),
+        1 => (x, y + 1),
+        2 => (x - 1, y),
+        3 => (x, y - 1),
+        _ => unreachable!(),
+    }
+}
+
+fn opposite(dir: u8) -> u8 {
+    (dir + 2) % 4
+}
+
+pub fn rail_connections(tile: &PlacedTile) -> Option<[u8; 2]> {
+    if tile.tile_type == tiles::RAIL || tile.tile_type == tiles::TRAIN_STOP {
+        Some([tile.rotation % 2, tile.rotation % 2 + 2])
+    } else if tile.tile_type == tiles::CURVED_RAIL {
+        Some([tile.rotation, (tile.rotation + 1) % 4])
+    } else {
+        None
+    }
+}
+
+/// Returns the direction in which a train leaves `cell` if it entered it while moving in direction `heading`.
+fn next_heading(game_world: &GameWorld, cell: (i32, i32), heading: u8) -> Option<u8> {
+    let [a, b] = rail_connections(game_world.tiles.get(&cell)?)?;
+    let entry = opposite(heading);
+    if a == entry {
+        Some(b)
+    } else if b == entry {
+        Some(a)
+    } else {
+        None
+    }
+}
+
+/// Breadth first search over the rail graph. The returned path does not include `start`.
+fn find_path(
+    game_world: &GameWorld,
+    start: (i32, i32),
+    heading: u8,
+    goal: (i32, i32),
+) -> Option<VecDeque<(i32, i32)>> {
+    let mut came_from = HashMap::new();
+    let mut queue = VecDeque::new();
+    queue.push_back((start, heading));
+
+    while let Some((cell, h)) = queue.pop_front() {
+        let next = step(cell, h);
+        let Some(next_h) = next_heading(game_world, next, h) else {
+            continue;
+        };
+        if next == start || came_from.contains_key(&(next, next_h)) {
+            continue;
+        }
+        came_from.insert((next, next_h), (cell, h));
+
+        if next == goal {
+            let mut path = VecDeque::new();
+            let mut state = (next, next_h);
+            while state.0 != start {
+                path.push_front(state.0);
+                state = came_from[&state];
+            }
+            return Some(path);
+        }
+        queue.push_back((next, next_h));
+    }
+    None
+}
+
+impl Train {
+    fn front(&self) -> (i32, i32) {
+        self.trail[0]
+    }
+
+    /// Lets the last car become the front of the train. Only valid between two cells.
+    fn reverse(&mut self, game_world: &GameWorld) {
+        let old_heading = self.heading;
+        self.trail.make_contiguous().reverse();
+        self.cars.reverse();
+
+        let towards_rest = if self.trail.len() > 1 {
+            let (x0, y0) = self.trail[0];
+            (0..4).find(|&d| step((x0, y0), d) == self.trail[1])
+        } else {
+            Some(old_heading)
+        };
+        if let Some(heading) =
+            towards_rest.and_then(|d| next_heading(game_world, self.front(), opposite(d)))
+        {
+            self.heading = heading;
+        }
+    }
+
+    fn wait_done(
+        &self,
+        wagons: &Query<&mut ItemContainer, With<CargoWagon>>,
+        condition: WaitCondition,
+    ) -> bool {
+        let mut wagons = self.cars.iter().filter_map(|&e| wagons.get(e).ok());
+        match condition {
+            WaitCondition::Full => wagons.all(|w| w.is_full()),
+            WaitCondition::Empty => wagons.all(|w| w.items.is_empty()),
+        }
+    }
+}
+
+/// Train whose schedule the player is editing.
+#[derive(Resource, Default)]
+pub struct ScheduleEditor {
+    train: Option<Entity>,
+    /// The HUD is rebuilt when the selection or the schedule changes.
+    changed: bool,
+}
+
+#[derive(Component)]
+pub struct ScheduleHud;
+
+/// Pressing G on a train selects it for editing. With a train selected, G on a train stop
+/// adds it to the schedule, or toggles the wait condition if it is already the last stop.
+/// Backspace removes the last stop, and G anywhere else finishes editing.
+pub fn edit_train_schedules(
+    q_windows: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<(&Camera, &GlobalTransform)>,
+    input_state: Res<InputState>,
+    rail_network: Res<RailNetwork>,
+    mut editor: ResMut<ScheduleEditor>,
+    mut q_trains: Query<&mut Train>,
+    q_stops: Query<(&PlacedTile, &TrainStop)>,
+) {
+    if input_state.inventory_ui.is_some() {
+        return;
+    }
+    if input_state.removing_schedule_entry {
+        if let Some(mut train) = editor.train.and_then(|e| q_trains.get_mut(e).ok()) {
+            train.schedule.pop();
+            editor.changed = true;
+        }
+        return;
+    }
+    if !input_state.toggling_schedule_editor {
+        return;
+    }
+
+    let (camera, camera_transform) = q_camera.single();
+    let Some(cell) = cursor_cell(q_windows.single(), camera, camera_transform) else {
+        return;
+    };
+    editor.changed = true;
+
+    if let Some(&train_entity) = rail_network.occupied.get(&cell) {
+        editor.train = if editor.train == Some(train_entity) {
+            None
+        } else {
+            Some(train_entity)
+        };
+        return;
+    }
+    let Some(mut train) = editor.train.and_then(|e| q_trains.get_mut(e).ok()) else {
+        editor.train = None;
+        return;
+    };
+    let Some((_, stop)) = q_stops.iter().find(|(t, _)| (t.x, t.y) == cell) else {
+        editor.train = None;
+        return;
+    };
+    match train.schedule.last_mut() {
+        Some(last) if last.station == stop.name => {
+            last.wait = match last.wait {
+                WaitCondition::Full => WaitCondition::Empty,
+                WaitCondition::Empty => WaitCondition::Full,
+            };
+        }
+        _ => train.schedule.push(ScheduleEntry {
+            station: stop.name.clone(),
+            wait: WaitCondition::Full,
+        }),
+    }
+}
+
+pub fn setup_schedule_hud(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(10.0),
+                top: Val::Px(10.0),
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            ..default()
+        },
+        ScheduleHud,
+    ));
+}
+
+/// Lists the schedule of the train being edited.
+pub fn update_schedule_hud(
+    mut commands: Commands,
+    mut editor: ResMut<ScheduleEditor>,
+    q_trains: Query<&Train>,
+    q_hud: Query<Entity, With<ScheduleHud>>,
+) {
+    if !editor.changed {
+        return;
+    }
+    editor.changed = false;
+
+    let hud = q_hud.single();
+    commands.entity(hud).despawn_descendants();
+    let Some(train) = editor.train.and_then(|e| q_trains.get(e).ok()) else {
+        return;
+    };
+    commands.entity(hud).with_children(|parent| {
+        ui::create_outlined_text(parent, "Train schedule".to_string());
+        if train.schedule.is_empty() {
+            ui::create_outlined_text(parent, "No stops, the train stays where it is".to_string());
+        }
+        for (i, entry) in train.schedule.iter().enumerate() {
+            ui::create_outlined_text(
+                parent,
+                format!(
+                    "{}. {}: wait until {}",
+                    i + 1,
+                    entry.station,
+                    entry.wait.label()
+                ),
+            );
+        }
+    });
+}
+
+pub fn update_trains(
+    mut q_trains: Query<(Entity, &mut Train)>,
+    mut q_wagons: Query<&mut ItemContainer, With<CargoWagon>>,
+    q_stops: Query<(&PlacedTile, &TrainStop)>,
+    mut rail_network: ResMut<RailNetwork>,
+    game_world: Res<GameWorld>,
+    time: Res<Time>,
+) {
+    for (train_entity, mut train) in q_trains.iter_mut() {
+        if train.schedule.is_empty() {
+            continue;
+        }
+        if train.current_stop >= train.schedule.len() {
+            train.current_stop = 0;
+        }
+
+        let entry = &train.schedule[train.current_stop];
+        let wait = entry.wait;
+        let Some(goal) = q_stops
+            .iter()
+            .find(|(_, stop)| stop.name == entry.station)
+            .map(|(tile, _)| (tile.x, tile.y))
+        else {
+            // the station no longer exists, so skip it
+            train.current_stop += 1;
+            continue;
+        };
+
+        match train.state {
+            TrainState::Waiting => {
+                if train.wait_done(&q_wagons, wait) {
+                    train.current_stop = (train.current_stop + 1) % train.schedule.len();
+                    train.state = TrainState::Moving;
+                    for &car in train.cars.iter() {
+                        if let Ok(mut wagon) = q_wagons.get_mut(car) {
+                            wagon.accessible = false;
+                        }
+                    }
+                }
+            }
+            TrainState::Moving => {
+                if train.path.is_empty() {
+                    if train.front() == goal {
+                        train.state = TrainState::Waiting;
+                        for &car in train.cars.iter() {
+                            if let Ok(mut wagon) = q_wagons.get_mut(car) {
+                                wagon.accessible = true;
+                            }
+                        }
+                        continue;
+                    }
+
+                    if let Some(path) = find_path(&game_world, train.front(), train.heading, goal) {
+                        train.path = path;
+                    } else {
+                        train.reverse(&game_world);
+                        if let Some(path) =
+                            find_path(&game_world, train.front(), train.heading, goal)
+                        {
+                            train.path = path;
+                        } else {
+                            train.reverse(&game_world);
+                        }
+                    }
+                    if train.path.is_empty() {
+                        continue;
+                    }
+                }
+
+                let next = train.path[0];
+                if rail_network
+                    .occupied
+                    .get(&next)
+                    .is_some_and(|&e| e != train_entity)
+                {
+                    continue;
+                }
+                let Some(next_heading) = next_heading(&game_world, next, train.heading) else {
+                    // the rail was removed
+                    train.path.clear();
+                    train.progress = 0.0;
+                    continue;
+                };
+
+                train.progress += time.delta_seconds() * TRAIN_SPEED;
+                if train.progress >= 1.0 {
+                    train.progress = 0.0;
+                    train.path.pop_front();
+                    train.heading = next_heading;
+                    train.trail.push_front(next);
+                    rail_network.occupied.insert(next, train_entity);
+                    if let Some(last) = train.trail.pop_back() {
+                        if !train.trail.contains(&last) {
+                            rail_network.occupied.remove(&last);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn cell_to_world((x, y): (i32, i32)) -> Vec2 {
+    Vec2::new(x as f32 * 32.0, y as f32 * 32.0)
+}
+
+pub fn update_train_sprites(
+    q_trains: Query<&Train>,
+    mut q_cars: Query<&mut Transform, With<TrainCar>>,
+) {
+    for train in q_trains.iter() {
+        for (i, &car) in train.cars.iter().enumerate() {
+            let Ok(mut transform) = q_cars.get_mut(car) else {
+                continue;
+            };
+            let Some(&from) = train.trail.get(i) else {
+                continue;
+            };
+            let to = if i == 0 {
+                train.path.front().copied().unwrap_or(from)
+            } else {
+                train.trail[i - 1]
+            };
+
+            let from = cell_to_world(from);
+            let to = cell_to_world(to);
+            let pos = from.lerp(to, train.progress);
+            let angle = if to != from {
+                (to - from).to_angle()
+            } else {
+                PI / 2.0 * train.heading as f32
+            };
+
+            transform.translation = pos.extend(Layer::Vehicle.depth());
+            transform.rotation = Quat::from_rotation_z(angle);
+        }
+    }
+}
+
+fn create_car_sprite(
+    asset_server: &Res<AssetServer>,
+    item: ItemType,
+    x: i32,
+    y: i32,
+    heading: u8,
+) -> impl Bundle {
+    SpriteBundle {
+        transform: Transform::from_rotation(Quat::from_rotation_z(PI / 2.0 * heading as f32))
+            .with_translation(vec3(
+                x as f32 * 32.0,
+                y as f32 * 32.0,
+                Layer::Vehicle.depth(),
+            )),
+        texture: asset_server.load(format!("textures/tiles/{}.png", item.texture_name)),
+        ..default()
+    }
+}
+
+/// Places locomotives and cargo wagons on the rails, and removes whole trains when they are deleted.
+pub fn handle_rolling_stock_actions(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    q_windows: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<(&Camera, &GlobalTransform)>,
+    mut q_player: Query<(&mut Player, &Transform, &actions::PlayerReach)>,
+    input_state: Res<InputState>,
+    game_world: Res<GameWorld>,
+    mut rail_network: ResMut<RailNetwork>,
+    mut q_trains: Query<&mut Train>,
+    q_cars: Query<&TrainCar>,
+    q_wagons: Query<&ItemContainer, With<CargoWagon>>,
+) {
+    if input_state.inventory_ui.is_some() {
+        return;
+    }
+    let (camera, camera_transform) = q_camera.single();
+    let Some(cell) = cursor_cell(q_windows.single(), camera, camera_transform) else {
+        return;
+    };
+    let (mut player, player_transform, reach) = q_player.single_mut();
+    let distance = (vec2(cell.0 as f32, cell.1 as f32) * 32.0)
+        .distance(player_transform.translation.truncate());
+
+    let deleting = input_state.deleting_tile
+        && distance <= reach.mining
+        && input_state
+            .deleting_tile_timer
+            .as_ref()
+            .is_some_and(|t| t.just_finished());
+    if deleting {
+        if let Some(&train_entity) = rail_network.occupied.get(&cell) {
+            if let Ok(train) = q_trains.get(train_entity) {
+                for &car in train.cars.iter() {
+                    if let Ok(c) = q_cars.get(car) {
+                        actions::give_or_drop(
+                            &mut commands,
+                            &asset_server,
+                            &mut player,
+                            c.item_to_drop,
+                            cell.0,
+                            cell.1,
+                        );
+                    }
+                    if let Ok(wagon) = q_wagons.get(car) {
+                        for &(item_type, count) in wagon.items.iter() {
+                            for _ in 0..count {
+                                actions::give_or_drop(
+                                    &mut commands,
+                                    &asset_server,
+                                    &mut player,
+                                    item_type,
+                                    cell.0,
+                                    cell.1,
+                                );
+                            }
+                        }
+                    }
+                    commands.entity(car).despawn();
+                }
+            }
+            rail_network.occupied.retain(|_, e| *e != train_entity);
+            commands.entity(train_entity).despawn();
+        }
+        return;
+    }
+
+    if input_state.drag_start.is_none() || distance > reach.build {
+        return;
+    }
+    let Some(item) = input_state.item_in_hand else {
+        return;
+    };
+    if item != items::LOCOMOTIVE && item != items::CARGO_WAGON {
+        return;
+    }
+    let Some(connections) = game_world.tiles.get(&cell).and_then(rail_connections) else {
+        return;
+    };
+    if rail_network.occupied.contains_key(&cell) || !player.has_item_in_inventory(item) {
+        return;
+    }
+
+    if item == items::LOCOMOTIVE {
+        player.decrement_inventory(item);
+
+        let heading = connections[0];
+        let train_entity = commands.spawn_empty().id();
+        let car = commands
+            .spawn((
+                create_car_sprite(&asset_server, item, cell.0, cell.1, heading),
+                TrainCar { item_to_drop: item },
+            ))
+            .id();
+        commands.entity(train_entity).insert(Train {
+            cars: vec![car],
+            trail: VecDeque::from([cell]),
+            heading,
+            path: VecDeque::new(),
+            progress: 0.0,
+            schedule: Vec::new(),
+            current_stop: 0,
+            state: TrainState::Moving,
+        });
+        rail_network.occupied.insert(cell, train_entity);
+    } else {
+        // a wagon has to be attached to the back of an existing train
+        for dir in connections {
+            let neighbour = step(cell, dir);
+            let Some(&train_entity) = rail_network.occupied.get(&neighbour) else {
+                continue;
+            };
+            let Ok(mut train) = q_trains.get_mut(train_entity) else {
+                continue;
+            };
+            if train.trail.back() != Some(&neighbour) || !train.path.is_empty() {
+                continue;
+            }
+            if next_heading(&game_world, neighbour, dir).is_none() {
+                continue;
+            }
+            if train.trail.len() == 1 && train.heading == opposite(dir) {
+                // the locomotive should drive away from its wagons
+                if let Some(heading) = next_heading(&game_world, neighbour, dir) {
+                    train.heading = heading;
+                }
+            }
+
+            player.decrement_inventory(item);
+
+            let car = commands
+                .spawn((
+                    create_car_sprite(&asset_server, item, cell.0, cell.1, opposite(dir)),
+                    TrainCar { item_to_drop: item },
+                    CargoWagon,
+                    ItemContainer {
+                        accessible: false,
+                        ..ItemContainer::new(WAGON_CAPACITY)
+                    },
+                ))
+                .id();
+            train.cars.push(car);
+            train.trail.push_back(cell);
+            rail_network.occupied.insert(cell, train_entity);
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn place(game_world: &mut GameWorld, tile_type: crate::TileType, rotation: u8, x: i32, y: i32) {
+        game_world.tiles.insert(
+            (x, y),
+            PlacedTile {
+                tile_type,
+                rotation,
+                x,
+                y,
+            },
+        );
+    }
+
+    #[test]
+    fn finds_path_along_straight_track() {
+        let mut game_world = GameWorld::default();
+        for x in 0..=5 {
+            place(&mut game_world, tiles::RAIL, 0, x, 0);
+        }
+
+        let path = find_path(&game_world, (0, 0), 0, (5, 0)).unwrap();
+        assert_eq!(
+            path,
+            VecDeque::from([(1, 0), (2, 0), (3, 0), (4, 0), (5, 0)])
+        );
+    }
+
+    #[test]
+    fn finds_path_through_curve() {
+        let mut game_world = GameWorld::default();
+        for x in 0..3 {
+            place(&mut game_world, tiles::RAIL, 0, x, 0);
+        }
+        // connects west and south
+        place(&mut game_world, tiles::CURVED_RAIL, 2, 3, 0);
+        for y in -2..0 {
+            place(&mut game_world, tiles::RAIL, 1, 3, y);
+        }
+
+        let path = find_path(&game_world, (0, 0), 0, (3, -2)).unwrap();
+        assert_eq!(
+            path,
+            VecDeque::from([(1, 0), (2, 0), (3, 0), (3, -1), (3, -2)])
+        );
+    }
+
+    #[test]
+    fn no_path_without_connecting_track() {
+        let mut game_world = GameWorld::default();
+        for x in 0..3 {
+            place(&mut game_world, tiles::RAIL, 0, x, 0);
+        }
+        place(&mut game_world, tiles::RAIL, 0, 5, 0);
+
+        assert_eq!(find_path(&game_world, (0, 0), 0, (5, 0)), None);
+        // facing away from the goal
+        assert_eq!(find_path(&game_world, (2, 0), 2, (5, 0)), None);
+    }
+}
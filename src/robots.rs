@@ -0,0 +1,495 @@
+use bevy::{math::vec2, prelude::*, window::PrimaryWindow};
+
+use crate::{
+    actions::{self, PlayerReach},
+    cursor_cell,
+    enemies::Enemy,
+    items,
+    placement::Placement,
+    rails::RailNetwork,
+    sprites, DroppedItem, GameWorld, GhostTile, InputState, ItemContainer, ItemType, Layer,
+    PlacedTile, Player, ResourceTile,
+};
+
+pub const CHEST_CAPACITY: usize = 100;
+const ROBOPORT_RANGE: i32 = 10;
+const ROBOT_SPEED: f32 = 96.0;
+const REQUEST_AMOUNT: usize = 20;
+
+#[derive(Component, Default)]
+pub struct Roboport {
+    logistic_robots: usize,
+    construction_robots: usize,
+}
+
+impl Roboport {
+    /// The robots stationed here, as items.
+    pub fn robot_items(&self) -> Vec<(ItemType, usize)> {
+        vec![
+            (items::LOGISTIC_ROBOT, self.logistic_robots),
+            (items::CONSTRUCTION_ROBOT, self.construction_robots),
+        ]
+    }
+}
+
+fn covers(roboport: &PlacedTile, x: i32, y: i32) -> bool {
+    (roboport.x - x).abs() <= ROBOPORT_RANGE && (roboport.y - y).abs() <= ROBOPORT_RANGE
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LogisticChestKind {
+    Provider,
+    Requester,
+    Storage,
+}
+
+#[derive(Component)]
+pub struct LogisticChest {
+    kind: LogisticChestKind,
+    pub request: Option<ItemType>,
+    /// Items that robots are on their way to deliver to this chest.
+    incoming: usize,
+    /// Items that robots are on their way to pick up from this chest.
+    outgoing: usize,
+}
+
+impl LogisticChest {
+    pub fn new(kind: LogisticChestKind) -> Self {
+        Self {
+            kind,
+            request: None,
+            incoming: 0,
+            outgoing: 0,
+        }
+    }
+
+    fn can_provide(&self, container: &ItemContainer, item: ItemType) -> bool {
+        self.kind != LogisticChestKind::Requester && container.count(item) > self.outgoing
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RobotKind {
+    Logistic,
+    Construction,
+}
+
+#[derive(Clone, Copy)]
+enum RobotJob {
+    Deliver {
+        from: Entity,
+        to: Entity,
+    },
+    /// Builds the ghost using an item from a chest, or from the player if `from` is `None`.
+    Build {
+        from: Option<Entity>,
+        ghost: Entity,
+    },
+}
+
+#[derive(Component)]
+pub struct Robot {
+    kind: RobotKind,
+    home: Entity,
+    item: ItemType,
+    carrying: bool,
+    /// Robots without a job fly back to their roboport.
+    job: Option<RobotJob>,
+}
+
+fn create_robot_sprite(asset_server: &Res<AssetServer>, kind: RobotKind, pos: Vec2) -> impl Bundle {
+    let texture_name = match kind {
+        RobotKind::Logistic => items::LOGISTIC_ROBOT.texture_name,
+        RobotKind::Construction => items::CONSTRUCTION_ROBOT.texture_name,
+    };
+    SpriteBundle {
+        transform: Transform::from_translation(pos.extend(Layer::Robot.depth())),
+        texture: asset_server.load(format!("textures/tiles/{}.png", texture_name)),
+        ..default()
+    }
+}
+
+fn drop_item(
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    item_type: ItemType,
+    pos: Vec2,
+) {
+    let item = DroppedItem { item_type };
+    commands.spawn((
+        sprites::create_dropped_item_sprite(
+            asset_server,
+            &item,
+            (pos.x + 8.0) / 32.0,
+            (pos.y - 8.0) / 32.0,
+        ),
+        item,
+    ));
+}
+
+/// Lets the player configure requester chests and put robots into roboports.
+pub fn handle_logistic_actions(
+    q_windows: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<(&Camera, &GlobalTransform)>,
+    mut q_player: Query<(&mut Player, &Transform, &PlayerReach)>,
+    input_state: Res<InputState>,
+    mut q_roboports: Query<(&PlacedTile, &mut Roboport)>,
+    mut q_chests: Query<(&PlacedTile, &mut LogisticChest)>,
+) {
+    if input_state.inventory_ui.is_some() {
+        return;
+    }
+    let (camera, camera_transform) = q_camera.single();
+    let Some((xx, yy)) = cursor_cell(q_windows.single(), camera, camera_transform) else {
+        return;
+    };
+
+    let (mut player, player_transform, reach) = q_player.single_mut();
+    let distance =
+        (vec2(xx as f32, yy as f32) * 32.0).distance(player_transform.translation.truncate());
+    if distance > reach.build {
+        return;
+    }
+    if input_state.setting_request {
+        if let Some((_, mut chest)) = q_chests
+            .iter_mut()
+            .find(|(t, c)| t.x == xx && t.y == yy && c.kind == LogisticChestKind::Requester)
+        {
+            chest.request = input_state.item_in_hand;
+        }
+    }
+
+    if input_state.drag_start.is_some() {
+        let Some(item) = input_state.item_in_hand else {
+            return;
+        };
+        if item != items::LOGISTIC_ROBOT && item != items::CONSTRUCTION_ROBOT {
+            return;
+        }
+        if let Some((_, mut roboport)) =
+            q_roboports.iter_mut().find(|(t, _)| t.x == xx && t.y == yy)
+        {
+            while player.decrement_inventory(item) {
+                if item == items::LOGISTIC_ROBOT {
+                    roboport.logistic_robots += 1;
+                } else {
+                    roboport.construction_robots += 1;
+                }
+            }
+        }
+    }
+}
+
+pub fn dispatch_robots(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut q_roboports: Query<(Entity, &PlacedTile, &Transform, &mut Roboport)>,
+    mut q_chests: Query<(Entity, &PlacedTile, &mut LogisticChest, &ItemContainer)>,
+    mut q_ghosts: Query<(Entity, &mut GhostTile)>,
+    q_player: Query<(&Player, &Transform)>,
+) {
+    let requests: Vec<(Entity, PlacedTile, ItemType)> = q_chests
+        .iter()
+        .filter_map(|(e, tile, chest, container)| {
+            let item = chest.request?;
+            (chest.kind == LogisticChestKind::Requester
+                && container.count(item) + chest.incoming < REQUEST_AMOUNT)
+                .then(|| (e, tile.clone(), item))
+        })
+        .collect();
+
+    for (requester, tile, item) in requests {
+        let Some((roboport, provider)) = q_roboports
+            .iter()
+            .filter(|(_, rt, _, r)| r.logistic_robots > 0 && covers(rt, tile.x, tile.y))
+            .find_map(|(re, rt, _, _)| {
+                q_chests
+                    .iter()
+                    .find(|(_, t, c, container)| {
+                        c.can_provide(container, item) && covers(rt, t.x, t.y)
+                    })
+                    .map(|(pe, ..)| (re, pe))
+            })
+        else {
+            continue;
+        };
+
+        let Ok((_, _, transform, mut port)) = q_roboports.get_mut(roboport) else {
+            continue;
+        };
+        port.logistic_robots -= 1;
+        commands.spawn((
+            create_robot_sprite(
+                &asset_server,
+                RobotKind::Logistic,
+                transform.translation.truncate(),
+            ),
+            Robot {
+                kind: RobotKind::Logistic,
+                home: roboport,
+                item,
+                carrying: false,
+                job: Some(RobotJob::Deliver {
+                    from: provider,
+                    to: requester,
+                }),
+            },
+        ));
+        if let Ok((_, _, mut chest, _)) = q_chests.get_mut(requester) {
+            chest.incoming += 1;
+        }
+        if let Ok((_, _, mut chest, _)) = q_chests.get_mut(provider) {
+            chest.outgoing += 1;
+        }
+    }
+
+    let player = q_player.get_single().ok();
+    for (ghost_entity, mut ghost) in q_ghosts.iter_mut() {
+        if ghost.claimed {
+            continue;
+        }
+        let (gx, gy) = (ghost.tile.x, ghost.tile.y);
+        let item = ghost.tile.tile_type.item_to_drop;
+
+        let Some((roboport, source)) = q_roboports
+            .iter()
+            .filter(|(_, rt, _, r)| r.construction_robots > 0 && covers(rt, gx, gy))
+            .find_map(|(re, rt, _, _)| {
+                if let Some((ce, ..)) = q_chests.iter().find(|(_, t, c, container)| {
+                    c.can_provide(container, item) && covers(rt, t.x, t.y)
+                }) {
+                    Some((re, Some(ce)))
+                } else if player.is_some_and(|(p, tr)| {
+                    let px = (tr.translation.x / 32.0 + 0.5).floor() as i32;
+                    let py = (tr.translation.y / 32.0 + 0.5).floor() as i32;
+                    p.has_item_in_inventory(item) && covers(rt, px, py)
+                }) {
+                    Some((re, None))
+                } else {
+                    None
+                }
+            })
+        else {
+            continue;
+        };
+
+        let Ok((_, _, transform, mut port)) = q_roboports.get_mut(roboport) else {
+            continue;
+        };
+        port.construction_robots -= 1;
+        ghost.claimed = true;
+        commands.spawn((
+            create_robot_sprite(
+                &asset_server,
+                RobotKind::Construction,
+                transform.translation.truncate(),
+            ),
+            Robot {
+                kind: RobotKind::Construction,
+                home: roboport,
+                item,
+                carrying: false,
+                job: Some(RobotJob::Build {
+                    from: source,
+                    ghost: ghost_entity,
+                }),
+            },
+        ));
+        if let Some(source) = source {
+            if let Ok((_, _, mut chest, _)) = q_chests.get_mut(source) {
+                chest.outgoing += 1;
+            }
+        }
+    }
+}
+
+/// Releases everything that was reserved for a job that will not be completed.
+fn cancel_job(
+    job: RobotJob,
+    source_released: bool,
+    q_chests: &mut Query<(&mut LogisticChest, &mut ItemContainer)>,
+    q_ghosts: &mut Query<&mut GhostTile>,
+) {
+    let source = match job {
+        RobotJob::Deliver { from, .. } => Some(from),
+        RobotJob::Build { from, .. } => from,
+    };
+    if let Some(source) = source.filter(|_| !source_released) {
+        if let Ok((mut chest, _)) = q_chests.get_mut(source) {
+            chest.outgoing = chest.outgoing.saturating_sub(1);
+        }
+    }
+    match job {
+        RobotJob::Deliver { to, .. } => {
+            if let Ok((mut chest, _)) = q_chests.get_mut(to) {
+                chest.incoming = chest.incoming.saturating_sub(1);
+            }
+        }
+        RobotJob::Build { ghost, .. } => {
+            if let Ok(mut ghost) = q_ghosts.get_mut(ghost) {
+                ghost.claimed = false;
+            }
+        }
+    }
+}
+
+/// Keeps the robots' transforms apart from the ones `Placement` reads.
+type RobotFilter = (Without<Player>, Without<Enemy>, Without<DroppedItem>);
+
+pub fn update_robots(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut q_robots: Query<(Entity, &mut Robot, &mut Transform), RobotFilter>,
+    mut q_roboports: Query<&mut Roboport>,
+    mut q_chests: Query<(&mut LogisticChest, &mut ItemContainer)>,
+    mut q_ghosts: Query<&mut GhostTile>,
+    mut q_player: Query<(&mut Player, &Transform), Without<Robot>>,
+    q_transforms: Query<&Transform, Without<Robot>>,
+    q_resource_tiles: Query<&ResourceTile>,
+    placement: Placement,
+    mut game_world: ResMut<GameWorld>,
+    mut rail_network: ResMut<RailNetwork>,
+    time: Res<Time>,
+) {
+    let player_pos = q_player.get_single().ok().map(|(_, tr)| tr.translation);
+
+    for (robot_entity, mut robot, mut transform) in q_robots.iter_mut() {
+        let pos = transform.translation.truncate();
+        let target = match robot.job {
+            None => q_transforms.get(robot.home).ok().map(|tr| tr.translation),
+            Some(RobotJob::Deliver { from, to }) => q_transforms
+                .get(if robot.carrying { to } else { from })
+                .ok()
+                .map(|tr| tr.translation),
+            Some(RobotJob::Build { from, ghost }) => {
+                if robot.carrying {
+                    q_transforms.get(ghost).ok().map(|tr| tr.translation)
+                } else if let Some(from) = from {
+                    q_transforms.get(from).ok().map(|tr| tr.translation)
+                } else {
+                    player_pos
+                }
+            }
+        };
+
+        let Some(target) = target.map(|t| t.truncate()) else {
+            if robot.carrying {
+                drop_item(&mut commands, &asset_server, robot.item, pos);
+            }
+            match robot.job {
+                Some(job) => {
+                    cancel_job(job, robot.carrying, &mut q_chests, &mut q_ghosts);
+                    robot.job = None;
+                    robot.carrying = false;
+                }
+                None => {
+                    // the roboport is gone, so the robot lands where it is
+                    let robot_item = match robot.kind {
+                        RobotKind::Logistic => items::LOGISTIC_ROBOT,
+                        RobotKind::Construction => items::CONSTRUCTION_ROBOT,
+                    };
+                    drop_item(&mut commands, &asset_server, robot_item, pos);
+                    commands.entity(robot_entity).despawn();
+                }
+            }
+            continue;
+        };
+
+        let delta = target - pos;
+        let step = ROBOT_SPEED * time.delta_seconds();
+        if delta.length() > step {
+            transform.translation += (delta.normalize() * step).extend(0.0);
+            continue;
+        }
+        transform.translation = target.extend(Layer::Robot.depth());
+
+        let Some(job) = robot.job else {
+            if let Ok(mut roboport) = q_roboports.get_mut(robot.home) {
+                match robot.kind {
+                    RobotKind::Logistic => roboport.logistic_robots += 1,
+                    RobotKind::Construction => roboport.construction_robots += 1,
+                }
+            }
+            commands.entity(robot_entity).despawn();
+            continue;
+        };
+
+        if !robot.carrying {
+            let source = match job {
+                RobotJob::Deliver { from, .. } => Some(from),
+                RobotJob::Build { from, .. } => from,
+            };
+            let picked_up = match source {
+                Some(source) => q_chests
+                    .get_mut(source)
+                    .is_ok_and(|(mut chest, mut container)| {
+                        chest.outgoing = chest.outgoing.saturating_sub(1);
+                        container.remove(robot.item)
+                    }),
+                None => q_player
+                    .get_single_mut()
+                    .is_ok_and(|(mut player, _)| player.decrement_inventory(robot.item)),
+            };
+            if picked_up {
+                robot.carrying = true;
+            } else {
+                cancel_job(job, true, &mut q_chests, &mut q_ghosts);
+                robot.job = None;
+            }
+            continue;
+        }
+
+        match job {
+            RobotJob::Deliver { to, .. } => {
+                let delivered = q_chests
+                    .get_mut(to)
+                    .is_ok_and(|(mut chest, mut container)| {
+                        chest.incoming = chest.incoming.saturating_sub(1);
+                        container.insert(robot.item)
+                    });
+                if !delivered {
+                    drop_item(&mut commands, &asset_server, robot.item, pos);
+                }
+            }
+            RobotJob::Build { ghost, .. } => {
+                let tile = q_ghosts
+                    .get(ghost)
+                    .ok()
+                    .map(|g| (g.tile.clone(), g.settings));
+                match tile {
+                    Some((tile, settings))
+                        if placement.is_valid(&game_world, tile.tile_type, tile.x, tile.y) =>
+                    {
+                        game_world.ghosts.remove(&(tile.x, tile.y));
+                        commands.entity(ghost).despawn();
+                        game_world.tiles.insert((tile.x, tile.y), tile.clone());
+
+                        let resource = q_resource_tiles
+                            .iter()
+                            .find(|t| t.x == tile.x && t.y == tile.y)
+                            .map(|t| t.resource_type);
+                        let entity = actions::spawn_tile(
+                            &mut commands,
+                            &asset_server,
+                            &mut rail_network,
+                            tile,
+                            resource,
+                        );
+                        if let Some(settings) = settings {
+                            actions::apply_tile_settings(&mut commands, entity, settings);
+                        }
+                    }
+                    // the ghost stays while the player, an enemy or an item is in the way
+                    Some((tile, _)) if !game_world.tiles.contains_key(&(tile.x, tile.y)) => {
+                        continue;
+                    }
+                    _ => {
+                        drop_item(&mut commands, &asset_server, robot.item, pos);
+                    }
+                }
+            }
+        }
+        robot.carrying = false;
+        robot.job = None;
+    }
+}
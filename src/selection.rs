@@ -0,0 +1,75 @@
+use bevy::{math::vec2, prelude::*, window::PrimaryWindow};
+
+use crate::{cursor_cell, InputState, SelectionTool};
+
+/// Sent when the player finishes dragging a rectangle with a selection tool.
+#[derive(Event)]
+pub struct AreaSelected {
+    pub tool: SelectionTool,
+    pub min: (i32, i32),
+    pub max: (i32, i32),
+}
+
+impl AreaSelected {
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.min.0 && x <= self.max.0 && y >= self.min.1 && y <= self.max.1
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct AreaSelection {
+    start: Option<(i32, i32)>,
+    mouse_was_down: bool,
+}
+
+fn tool_color(tool: SelectionTool) -> Color {
+    match tool {
+        SelectionTool::Blueprint => Color::srgb(0.3, 0.6, 1.0),
+        SelectionTool::Deconstruction => Color::srgb(0.9, 0.2, 0.2),
+        SelectionTool::Upgrade => Color::srgb(0.3, 0.9, 0.3),
+    }
+}
+
+pub fn select_area(
+    q_windows: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<(&Camera, &GlobalTransform)>,
+    input_state: Res<InputState>,
+    mut selection: ResMut<AreaSelection>,
+    mut area_selected: EventWriter<AreaSelected>,
+    mut gizmos: Gizmos,
+) {
+    let mouse_down = input_state.drag_start.is_some();
+    let clicked = mouse_down && !selection.mouse_was_down;
+    selection.mouse_was_down = mouse_down;
+
+    let Some(tool) = input_state.selection_tool else {
+        selection.start = None;
+        return;
+    };
+    let (camera, camera_transform) = q_camera.single();
+    let Some((xx, yy)) = cursor_cell(q_windows.single(), camera, camera_transform) else {
+        return;
+    };
+
+    if clicked {
+        selection.start = Some((xx, yy));
+    }
+    let Some(start) = selection.start else {
+        return;
+    };
+    let center = vec2((start.0 + xx) as f32, (start.1 + yy) as f32) * 16.0;
+    let size = vec2(
+        ((start.0 - xx).abs() + 1) as f32,
+        ((start.1 - yy).abs() + 1) as f32,
+    ) * 32.0;
+    gizmos.rect_2d(center, 0.0, size, tool_color(tool));
+
+    if !mouse_down {
+        selection.start = None;
+        area_selected.send(AreaSelected {
+            tool,
+            min: (start.0.min(xx), start.1.min(yy)),
+            max: (start.0.max(xx), start.1.max(yy)),
+        });
+    }
+}
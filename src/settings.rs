@@ -0,0 +1,70 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+
+use crate::{
+    blueprints::BlueprintState, cursor_cell, robots::LogisticChest, tiles, GameWorld, InputState,
+    PlacedTile, TileSettings, TileType,
+};
+
+/// Settings copied with shift + right click, together with the type of tile they came from.
+#[derive(Resource, Default)]
+pub struct CopiedSettings {
+    source: Option<(TileType, TileSettings)>,
+}
+
+fn settings_of(tile_type: TileType, chest: &LogisticChest) -> Option<TileSettings> {
+    if tile_type != tiles::REQUESTER_CHEST {
+        return None;
+    }
+    chest.request.map(TileSettings::Request)
+}
+
+/// Shift + right click copies the settings of the hovered tile, shift + left click pastes them
+/// onto every tile of the same type the mouse is dragged over.
+pub fn copy_paste_settings(
+    q_windows: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<(&Camera, &GlobalTransform)>,
+    input_state: Res<InputState>,
+    blueprint_state: Res<BlueprintState>,
+    game_world: Res<GameWorld>,
+    mut copied: ResMut<CopiedSettings>,
+    mut q_chests: Query<(&PlacedTile, &mut LogisticChest)>,
+) {
+    // with a blueprint in hand shift + left click pastes the blueprint instead
+    let pasting = input_state.pasting_settings && !blueprint_state.has_blueprint();
+    if !input_state.copying_settings && !pasting {
+        return;
+    }
+    let (camera, camera_transform) = q_camera.single();
+    let Some((xx, yy)) = cursor_cell(q_windows.single(), camera, camera_transform) else {
+        return;
+    };
+    let Some(tile) = game_world.tiles.get(&(xx, yy)) else {
+        return;
+    };
+
+    if input_state.copying_settings {
+        if let Some(settings) = q_chests
+            .iter()
+            .find(|(t, _)| t.x == xx && t.y == yy)
+            .and_then(|(_, chest)| settings_of(tile.tile_type, chest))
+        {
+            copied.source = Some((tile.tile_type, settings));
+        }
+        return;
+    }
+
+    let Some((tile_type, settings)) = copied.source else {
+        return;
+    };
+    if tile.tile_type != tile_type {
+        return;
+    }
+    match settings {
+        TileSettings::Request(item_type) => {
+            if let Some((_, mut chest)) = q_chests.iter_mut().find(|(t, _)| t.x == xx && t.y == yy)
+            {
+                chest.request = Some(item_type);
+            }
+        }
+    }
+}
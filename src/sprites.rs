@@ -31,6 +31,7 @@ pub fn create_preview_sprite(
     x: i32,
     y: i32,
     rotation: u8,
+    color: Color,
 ) -> impl Bundle {
     let item_texture = asset_server.load(format!("textures/tiles/{}.png", tile_type.texture_name));
 
@@ -40,7 +41,54 @@ pub fn create_preview_sprite(
             .with_translation(vec3(x as f32 * 32.0, y as f32 * 32.0, Layer::Tile.depth())),
         texture: item_texture.clone(),
         sprite: Sprite {
-            color: Color::srgba(1.0, 1.0, 1.0, 0.7),
+            color,
+            ..Default::default()
+        },
+        ..default()
+    }
+}
+
+pub fn create_ghost_sprite(asset_server: &Res<AssetServer>, tile: &PlacedTile) -> impl Bundle {
+    let item_texture = asset_server.load(format!(
+        "textures/tiles/{}.png",
+        tile.tile_type.texture_name
+    ));
+
+    SpriteBundle {
+        transform: Transform::from_scale(Vec3::splat(1.0))
+            .with_rotation(Quat::from_rotation_z(PI / 2.0 * tile.rotation as f32))
+            .with_translation(vec3(
+                tile.x as f32 * 32.0,
+                tile.y as f32 * 32.0,
+                Layer::Tile.depth(),
+            )),
+        texture: item_texture.clone(),
+        sprite: Sprite {
+            color: Color::srgba(0.6, 0.8, 1.0, 0.4),
+            ..Default::default()
+        },
+        ..default()
+    }
+}
+
+/// Darkened copy of a destroyed tile, left behind for a while.
+pub fn create_remnants_sprite(asset_server: &Res<AssetServer>, tile: &PlacedTile) -> impl Bundle {
+    let item_texture = asset_server.load(format!(
+        "textures/tiles/{}.png",
+        tile.tile_type.texture_name
+    ));
+
+    SpriteBundle {
+        transform: Transform::from_scale(Vec3::splat(1.0))
+            .with_rotation(Quat::from_rotation_z(PI / 2.0 * tile.rotation as f32))
+            .with_translation(vec3(
+                tile.x as f32 * 32.0,
+                tile.y as f32 * 32.0,
+                Layer::Remnants.depth(),
+            )),
+        texture: item_texture.clone(),
+        sprite: Sprite {
+            color: Color::srgba(0.25, 0.2, 0.2, 0.8),
             ..Default::default()
         },
         ..default()
@@ -49,32 +97,21 @@ pub fn create_preview_sprite(
 
 pub fn create_rotating_preview_sprite(
     asset_server: &Res<AssetServer>,
-    tile_type: TileType,
-    x: i32,
-    y: i32,
-    rotation: u8,
+    tile: &PlacedTile,
     anchor: Vec2,
     start_angle: f32,
+    color: Color,
 ) -> impl Bundle {
     let item_texture = asset_server.load(format!(
         "textures/tiles/{}.png",
-        tile_type.rotating_texture_name.unwrap()
+        tile.tile_type.rotating_texture_name.unwrap()
     ));
 
     SpriteBundle {
-        transform: calc_rotating_tile_transform(
-            &PlacedTile {
-                tile_type,
-                rotation,
-                x,
-                y,
-            },
-            anchor,
-            start_angle,
-        ),
+        transform: calc_rotating_tile_transform(tile, anchor, start_angle),
         texture: item_texture.clone(),
         sprite: Sprite {
-            color: Color::srgba(1.0, 1.0, 1.0, 0.7),
+            color,
             ..Default::default()
         },
         ..default()
@@ -132,3 +169,21 @@ pub fn create_resource_sprite(asset_server: &Res<AssetServer>, tile: &ResourceTi
         ..default()
     }
 }
+
+pub fn create_label_sprite(text: String, parent_rotation: u8) -> impl Bundle {
+    Text2dBundle {
+        text: Text::from_section(
+            text,
+            TextStyle {
+                color: Color::hsv(0.0, 0.0, 0.9),
+                font_size: 14.0,
+                ..default()
+            },
+        ),
+        transform: Transform::from_rotation(Quat::from_rotation_z(
+            -PI / 2.0 * parent_rotation as f32,
+        ))
+        .with_translation(vec3(0.0, 0.0, 0.01)),
+        ..default()
+    }
+}
@@ -0,0 +1,397 @@
+use std::collections::VecDeque;
+
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{ui, InputState, ItemType};
+
+/// The longest window we keep statistics for, in seconds.
+const MAX_HISTORY: usize = 3600;
+const GRAPH_POINTS: usize = 30;
+const GRAPH_HEIGHT: f32 = 32.0;
+const COLOR_PANEL_BG: Color = Color::hsv(0.0, 0.0, 0.2);
+const COLOR_BUTTON_NORMAL: Color = Color::hsv(0.0, 0.0, 0.3);
+const COLOR_BUTTON_SELECTED: Color = Color::hsv(0.0, 0.0, 0.45);
+const COLOR_PRODUCED: Color = Color::srgb(0.2, 0.8, 0.2);
+const COLOR_CONSUMED: Color = Color::srgb(0.85, 0.25, 0.2);
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StatsWindow {
+    OneMinute,
+    TenMinutes,
+    OneHour,
+}
+
+impl StatsWindow {
+    const ALL: [StatsWindow; 3] = [
+        StatsWindow::OneMinute,
+        StatsWindow::TenMinutes,
+        StatsWindow::OneHour,
+    ];
+
+    pub fn seconds(&self) -> usize {
+        match self {
+            StatsWindow::OneMinute => 60,
+            StatsWindow::TenMinutes => 600,
+            StatsWindow::OneHour => MAX_HISTORY,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            StatsWindow::OneMinute => "1m",
+            StatsWindow::TenMinutes => "10m",
+            StatsWindow::OneHour => "1h",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Bucket {
+    produced: HashMap<ItemType, u32>,
+    consumed: HashMap<ItemType, u32>,
+}
+
+/// Counts of produced and consumed items, kept in one bucket per second.
+/// The last bucket is the second that is currently being recorded.
+#[derive(Resource)]
+pub struct ProductionStats {
+    buckets: VecDeque<Bucket>,
+    timer: Timer,
+}
+
+impl Default for ProductionStats {
+    fn default() -> Self {
+        Self {
+            buckets: VecDeque::from([Bucket::default()]),
+            timer: Timer::from_seconds(1.0, TimerMode::Repeating),
+        }
+    }
+}
+
+impl ProductionStats {
+    pub fn record_produced(&mut self, item_type: ItemType, count: u32) {
+        let bucket = self.buckets.back_mut().unwrap();
+        *bucket.produced.entry(item_type).or_default() += count;
+    }
+
+    pub fn record_consumed(&mut self, item_type: ItemType, count: u32) {
+        let bucket = self.buckets.back_mut().unwrap();
+        *bucket.consumed.entry(item_type).or_default() += count;
+    }
+
+    /// Closes the current second and starts recording the next one.
+    pub fn advance(&mut self) {
+        self.buckets.push_back(Bucket::default());
+        if self.buckets.len() > MAX_HISTORY {
+            self.buckets.pop_front();
+        }
+    }
+
+    fn window(&self, window: StatsWindow) -> impl Iterator<Item = &Bucket> {
+        self.buckets.iter().rev().take(window.seconds())
+    }
+
+    pub fn produced(&self, item_type: ItemType, window: StatsWindow) -> u32 {
+        self.window(window)
+            .filter_map(|b| b.produced.get(&item_type))
+            .sum()
+    }
+
+    pub fn consumed(&self, item_type: ItemType, window: StatsWindow) -> u32 {
+        self.window(window)
+            .filter_map(|b| b.consumed.get(&item_type))
+            .sum()
+    }
+
+    /// Splits the window into `points` equally long intervals and returns the
+    /// produced and consumed counts of each, oldest first.
+    pub fn history(
+        &self,
+        item_type: ItemType,
+        window: StatsWindow,
+        points: usize,
+    ) -> Vec<(u32, u32)> {
+        let per_point = window.seconds().div_ceil(points);
+        let mut history = vec![(0, 0); points];
+        for (age, bucket) in self.window(window).enumerate() {
+            let point = &mut history[points - 1 - age / per_point];
+            point.0 += bucket.produced.get(&item_type).copied().unwrap_or(0);
+            point.1 += bucket.consumed.get(&item_type).copied().unwrap_or(0);
+        }
+        history
+    }
+
+    /// All item types that were produced or consumed within the window.
+    pub fn item_types(&self, window: StatsWindow) -> Vec<ItemType> {
+        let mut item_types: Vec<ItemType> = self
+            .window(window)
+            .flat_map(|b| b.produced.keys().chain(b.consumed.keys()))
+            .copied()
+            .collect();
+        item_types.sort_by_key(|t| t.texture_name);
+        item_types.dedup();
+        item_types
+    }
+}
+
+#[derive(Resource)]
+pub struct StatsPanel {
+    root: Option<Entity>,
+    window: StatsWindow,
+    refresh_timer: Timer,
+}
+
+impl Default for StatsPanel {
+    fn default() -> Self {
+        Self {
+            root: None,
+            window: StatsWindow::OneMinute,
+            refresh_timer: Timer::from_seconds(1.0, TimerMode::Repeating),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct StatsWindowButton {
+    window: StatsWindow,
+}
+
+pub fn update_production_stats(mut stats: ResMut<ProductionStats>, time: Res<Time>) {
+    if stats.timer.tick(time.delta()).just_finished() {
+        stats.advance();
+    }
+}
+
+pub fn handle_production_stats_ui(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    input_state: Res<InputState>,
+    stats: Res<ProductionStats>,
+    mut panel: ResMut<StatsPanel>,
+    q_buttons: Query<(&StatsWindowButton, &Interaction), Changed<Interaction>>,
+    time: Res<Time>,
+) {
+    let mut rebuild = false;
+    if input_state.toggling_stats_visible {
+        if let Some(root) = panel.root.take() {
+            commands.entity(root).despawn_recursive();
+        } else {
+            rebuild = true;
+        }
+    }
+    if panel.root.is_none() && !rebuild {
+        return;
+    }
+
+    for (button, interaction) in q_buttons.iter() {
+        if *interaction == Interaction::Pressed {
+            panel.window = button.window;
+            rebuild = true;
+        }
+    }
+    if panel.refresh_timer.tick(time.delta()).just_finished() {
+        rebuild = true;
+    }
+
+    if rebuild {
+        if let Some(root) = panel.root.take() {
+            commands.entity(root).despawn_recursive();
+        }
+        panel.root = Some(create_production_stats_ui(
+            commands,
+            &asset_server,
+            &stats,
+            panel.window,
+        ));
+    }
+}
+
+fn create_production_stats_ui(
+    mut commands: Commands,
+    asset_server: &Res<AssetServer>,
+    stats: &ProductionStats,
+    window: StatsWindow,
+) -> Entity {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(8.0),
+                top: Val::Px(8.0),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(4.0)),
+                ..default()
+            },
+            background_color: BackgroundColor(COLOR_PANEL_BG),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for w in StatsWindow::ALL {
+                        parent
+                            .spawn((
+                                ButtonBundle {
+                                    style: Style {
+                                        padding: UiRect::all(Val::Px(4.0)),
+                                        margin: UiRect::all(Val::Px(1.0)),
+                                        ..default()
+                                    },
+                                    background_color: BackgroundColor(if w == window {
+                                        COLOR_BUTTON_SELECTED
+                                    } else {
+                                        COLOR_BUTTON_NORMAL
+                                    }),
+                                    ..default()
+                                },
+                                StatsWindowButton { window: w },
+                            ))
+                            .with_children(|parent| {
+                                ui::create_outlined_text(parent, w.label().to_string());
+                            });
+                    }
+                });
+
+            for item_type in stats.item_types(window) {
+                let history = stats.history(item_type, window, GRAPH_POINTS);
+                let max = history
+                    .iter()
+                    .map(|(p, c)| *p.max(c))
+                    .max()
+                    .unwrap_or(0)
+                    .max(1);
+
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::End,
+                            margin: UiRect::all(Val::Px(1.0)),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn((
+                            NodeBundle {
+                                style: Style {
+                                    width: Val::Px(32.0),
+                                    height: Val::Px(32.0),
+                                    ..default()
+                                },
+                                ..default()
+                            },
+                            UiImage::new(
+                                asset_server
+                                    .load(format!("textures/items/{}.png", item_type.texture_name)),
+                            ),
+                        ));
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    width: Val::Px(90.0),
+                                    ..default()
+                                },
+                                ..default()
+                            })
+                            .with_children(|parent| {
+                                ui::create_outlined_text(
+                                    parent,
+                                    format!(
+                                        "+{} / -{}",
+                                        stats.produced(item_type, window),
+                                        stats.consumed(item_type, window)
+                                    ),
+                                );
+                            });
+                        for (produced, consumed) in history {
+                            for (count, color) in
+                                [(produced, COLOR_PRODUCED), (consumed, COLOR_CONSUMED)]
+                            {
+                                parent.spawn(NodeBundle {
+                                    style: Style {
+                                        width: Val::Px(2.0),
+                                        height: Val::Px(GRAPH_HEIGHT * count as f32 / max as f32),
+                                        ..default()
+                                    },
+                                    background_color: BackgroundColor(color),
+                                    ..default()
+                                });
+                            }
+                            parent.spawn(NodeBundle {
+                                style: Style {
+                                    width: Val::Px(1.0),
+                                    ..default()
+                                },
+                                ..default()
+                            });
+                        }
+                    });
+            }
+        })
+        .id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items;
+
+    #[test]
+    fn sums_counts_within_windows() {
+        let mut stats = ProductionStats::default();
+        stats.record_produced(items::COAL, 1);
+        for _ in 0..1000 {
+            stats.advance();
+        }
+        stats.record_produced(items::COAL, 10);
+        for _ in 0..300 {
+            stats.advance();
+        }
+        stats.record_produced(items::COAL, 100);
+        stats.record_consumed(items::COAL, 4);
+
+        assert_eq!(stats.produced(items::COAL, StatsWindow::OneMinute), 100);
+        assert_eq!(stats.produced(items::COAL, StatsWindow::TenMinutes), 110);
+        assert_eq!(stats.produced(items::COAL, StatsWindow::OneHour), 111);
+        assert_eq!(stats.consumed(items::COAL, StatsWindow::OneMinute), 4);
+        assert_eq!(stats.produced(items::IRON_ORE, StatsWindow::OneHour), 0);
+    }
+
+    #[test]
+    fn advance_drops_buckets_older_than_history() {
+        let mut stats = ProductionStats::default();
+        stats.record_produced(items::COAL, 1);
+        for _ in 0..MAX_HISTORY - 1 {
+            stats.advance();
+        }
+        assert_eq!(stats.produced(items::COAL, StatsWindow::OneHour), 1);
+
+        stats.advance();
+        assert_eq!(stats.buckets.len(), MAX_HISTORY);
+        assert_eq!(stats.produced(items::COAL, StatsWindow::OneHour), 0);
+    }
+
+    #[test]
+    fn history_groups_buckets_oldest_first() {
+        let mut stats = ProductionStats::default();
+        stats.record_produced(items::COAL, 7);
+        for _ in 0..25 {
+            stats.advance();
+        }
+        stats.record_produced(items::COAL, 5);
+        stats.record_consumed(items::COAL, 2);
+
+        assert_eq!(
+            stats.history(items::COAL, StatsWindow::OneMinute, 3),
+            vec![(0, 0), (7, 0), (5, 2)]
+        );
+    }
+}
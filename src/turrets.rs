@@ -0,0 +1,100 @@
+use bevy::{math::vec2, prelude::*, window::PrimaryWindow};
+
+use crate::{
+    actions::PlayerReach, cursor_cell, enemies::Enemy, health::DamageEvent, items, InputState,
+    ItemContainer, PlacedTile, Player,
+};
+
+pub const TURRET_CAPACITY: usize = 20;
+const TURRET_RANGE: f32 = 8.0 * 32.0;
+const TURRET_DAMAGE: f32 = 6.0;
+const FIRE_INTERVAL: f32 = 0.3;
+/// Number of shots a single ammo item is good for.
+pub const SHOTS_PER_AMMO: u32 = 10;
+
+#[derive(Component)]
+pub struct GunTurret {
+    fire_timer: Timer,
+    shots_left: u32,
+}
+
+impl Default for GunTurret {
+    fn default() -> Self {
+        Self {
+            fire_timer: Timer::from_seconds(FIRE_INTERVAL, TimerMode::Once),
+            shots_left: 0,
+        }
+    }
+}
+
+/// Clicking a turret with ammo in hand loads all of it.
+pub fn handle_turret_actions(
+    q_windows: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<(&Camera, &GlobalTransform)>,
+    mut q_player: Query<(&mut Player, &Transform, &PlayerReach)>,
+    input_state: Res<InputState>,
+    mut q_turrets: Query<(&PlacedTile, &mut ItemContainer), With<GunTurret>>,
+) {
+    if input_state.inventory_ui.is_some()
+        || input_state.drag_start.is_none()
+        || input_state.item_in_hand != Some(items::AMMO)
+    {
+        return;
+    }
+    let (camera, camera_transform) = q_camera.single();
+    let Some((xx, yy)) = cursor_cell(q_windows.single(), camera, camera_transform) else {
+        return;
+    };
+
+    let (mut player, player_transform, reach) = q_player.single_mut();
+    let distance =
+        (vec2(xx as f32, yy as f32) * 32.0).distance(player_transform.translation.truncate());
+    if distance > reach.build {
+        return;
+    }
+    if let Some((_, mut container)) = q_turrets.iter_mut().find(|(t, _)| t.x == xx && t.y == yy) {
+        while !container.is_full() && player.decrement_inventory(items::AMMO) {
+            container.insert(items::AMMO);
+        }
+    }
+}
+
+pub fn update_turrets(
+    mut q_turrets: Query<(&mut GunTurret, &mut ItemContainer, &mut Transform), Without<Enemy>>,
+    q_enemies: Query<(Entity, &Transform), With<Enemy>>,
+    mut damage_events: EventWriter<DamageEvent>,
+    time: Res<Time>,
+) {
+    for (mut turret, mut container, mut transform) in q_turrets.iter_mut() {
+        turret.fire_timer.tick(time.delta());
+        if !turret.fire_timer.finished() {
+            continue;
+        }
+
+        let pos = transform.translation.truncate();
+        let Some((enemy, enemy_transform)) = q_enemies
+            .iter()
+            .map(|(e, tr)| (e, tr.translation.truncate()))
+            .filter(|(_, p)| p.distance(pos) <= TURRET_RANGE)
+            .min_by(|(_, a), (_, b)| a.distance(pos).total_cmp(&b.distance(pos)))
+        else {
+            continue;
+        };
+
+        if turret.shots_left == 0 {
+            if !container.remove(items::AMMO) {
+                continue;
+            }
+            turret.shots_left = SHOTS_PER_AMMO;
+        }
+        turret.shots_left -= 1;
+        turret.fire_timer.reset();
+
+        let dir = enemy_transform - pos;
+        transform.rotation = Quat::from_rotation_z(dir.y.atan2(dir.x));
+        damage_events.send(DamageEvent {
+            target: enemy,
+            amount: TURRET_DAMAGE,
+        });
+    }
+}
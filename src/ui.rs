@@ -1,6 +1,11 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, window::PrimaryWindow};
 
-use crate::{items, InputState, ItemType, Player};
+use crate::{
+    crafting::{self, CraftingQueue, RecipeCategory},
+    InputState, ItemType, Player, INVENTORY_SLOTS,
+};
+
+const INVENTORY_COLUMNS: usize = 10;
 
 const COLOR_ITEM_BORDER: Color = Color::hsv(0.0, 0.0, 0.2);
 const COLOR_ITEM_BG_NORMAL: Color = Color::hsv(0.0, 0.0, 0.3);
@@ -16,9 +21,31 @@ pub struct CraftableItem {
     item_type: Option<ItemType>,
 }
 
+#[derive(Component)]
+pub struct CategoryTab {
+    category: RecipeCategory,
+}
+
+#[derive(Component)]
+pub struct Tooltip;
+
+#[derive(Component)]
+pub struct SortButton;
+
+/// Container of the recipes of the selected category.
+#[derive(Component)]
+pub struct RecipeGrid;
+
+#[derive(Resource, Default)]
+pub struct SelectedCategory(RecipeCategory);
+
+/// Inventory slot the player started dragging from.
+#[derive(Resource, Default)]
+pub struct DraggedSlot(Option<usize>);
+
 pub fn hanle_player_inventory_ui_events(
-    mut input_state: ResMut<InputState>,
     mut q_player: Query<&mut Player>,
+    mut queue: ResMut<CraftingQueue>,
     mut q_inventory_item_int: Query<
         (&InventoryItem, &mut BackgroundColor, &Interaction),
         Changed<Interaction>,
@@ -28,15 +55,10 @@ pub fn hanle_player_inventory_ui_events(
         (Changed<Interaction>, Without<InventoryItem>),
     >,
 ) {
-    for (item, mut bg, interaction) in q_inventory_item_int.iter_mut() {
+    for (_, mut bg, interaction) in q_inventory_item_int.iter_mut() {
         match interaction {
-            Interaction::Pressed => {
-                let inv = &q_player.single().inventory;
-                if item.idx < inv.len() {
-                    // TODO: remove from inventory
-                    input_state.item_in_hand = Some(inv[item.idx].0);
-                }
-            }
+            // picking into hand happens on release, see handle_inventory_slot_moves
+            Interaction::Pressed => {}
             Interaction::Hovered => {
                 bg.0 = COLOR_ITEM_BG_HOVER;
             }
@@ -49,36 +71,8 @@ pub fn hanle_player_inventory_ui_events(
     for (item, mut bg, interaction) in q_craftable_item_int.iter_mut() {
         match interaction {
             Interaction::Pressed => {
-                let mut player = q_player.single_mut();
                 if let Some(item_type) = item.item_type {
-                    match item_type {
-                        i if i == items::BELT => {
-                            if player.has_item_in_inventory(items::IRON_SHEET) {
-                                player.decrement_inventory(items::IRON_SHEET);
-                                player.increment_inventory(items::BELT);
-                            }
-                        }
-                        i if i == items::INSERTER => {
-                            if player.has_item_in_inventory(items::IRON_SHEET) && player.has_item_in_inventory(items::COPPER_SHEET) {
-                                player.decrement_inventory(items::IRON_SHEET);
-                                player.decrement_inventory(items::COPPER_SHEET);
-                                player.increment_inventory(items::INSERTER);
-                            }
-                        }
-                        i if i == items::FURNACE => {
-                            if player.has_item_in_inventory(items::IRON_SHEET) {
-                                player.decrement_inventory(items::IRON_SHEET);
-                                player.increment_inventory(items::FURNACE);
-                            }
-                        }
-                        i if i == items::MINER => {
-                            if player.has_item_in_inventory(items::IRON_SHEET) {
-                                player.decrement_inventory(items::IRON_SHEET);
-                                player.increment_inventory(items::MINER);
-                            }
-                        }
-                        _ => {}
-                    }
+                    crafting::queue_craft(&mut queue, &mut q_player.single_mut(), item_type);
                 }
             }
             Interaction::Hovered => {
@@ -91,11 +85,189 @@ pub fn hanle_player_inventory_ui_events(
     }
 }
 
-pub fn create_player_inventory_ui(
+/// Drags stacks between inventory slots with the left button, splits them with the right
+/// button and sorts the inventory with the sort button.
+pub fn handle_inventory_slot_moves(
+    buttons: Res<ButtonInput<MouseButton>>,
+    mut dragged: ResMut<DraggedSlot>,
+    mut input_state: ResMut<InputState>,
+    mut q_player: Query<&mut Player>,
+    q_slots: Query<(&InventoryItem, &Interaction)>,
+    q_sort_buttons: Query<&Interaction, (Changed<Interaction>, With<SortButton>)>,
+) {
+    let mut player = q_player.single_mut();
+    let hovered = q_slots
+        .iter()
+        .find(|(_, interaction)| **interaction == Interaction::Hovered)
+        .map(|(slot, _)| slot.idx);
+
+    if buttons.just_pressed(MouseButton::Left) {
+        dragged.0 = q_slots
+            .iter()
+            .find(|(_, interaction)| **interaction == Interaction::Pressed)
+            .map(|(slot, _)| slot.idx);
+    }
+    if buttons.just_released(MouseButton::Left) {
+        match (dragged.0.take(), hovered) {
+            // released over the same slot, a click rather than a drag
+            (Some(from), Some(to)) if from == to => {
+                if let Some(Some((item_type, _))) = player.inventory.get(from) {
+                    input_state.item_in_hand = Some(*item_type);
+                }
+            }
+            (Some(from), Some(to)) => player.move_stack(from, to),
+            _ => {}
+        }
+    }
+    if buttons.just_pressed(MouseButton::Right) {
+        if let Some(idx) = hovered {
+            player.split_stack(idx);
+        }
+    }
+
+    if q_sort_buttons
+        .iter()
+        .any(|interaction| *interaction == Interaction::Pressed)
+    {
+        player.sort_inventory();
+    }
+}
+
+/// Keeps the inventory slots in sync with the player inventory, and dims the recipes
+/// that can't be crafted with what the player has.
+pub fn update_inventory_ui(
     mut commands: Commands,
-    asset_server: &Res<AssetServer>,
-    inventory: &[(ItemType, usize)],
-) -> Entity {
+    asset_server: Res<AssetServer>,
+    q_player: Query<Ref<Player>>,
+    q_slots: Query<(Entity, Ref<InventoryItem>)>,
+    q_craftables: Query<(Ref<CraftableItem>, &Children)>,
+    mut q_images: Query<&mut UiImage>,
+) {
+    let player = q_player.single();
+    for (entity, slot) in q_slots.iter() {
+        if !player.is_changed() && !slot.is_added() {
+            continue;
+        }
+        let inv = player.inventory.get(slot.idx).copied().flatten();
+        commands.entity(entity).despawn_descendants();
+        commands.entity(entity).with_children(|parent| {
+            spawn_inventory_slot(parent, &asset_server, inv);
+        });
+    }
+
+    for (craftable, children) in q_craftables.iter() {
+        // only recompute when the inventory changed or the grid was rebuilt
+        if !player.is_changed() && !craftable.is_added() {
+            continue;
+        }
+        let Some(item_type) = craftable.item_type else {
+            continue;
+        };
+        let alpha = if crafting::can_craft(&player, item_type) {
+            1.0
+        } else {
+            0.3
+        };
+        for &child in children.iter() {
+            if let Ok(mut image) = q_images.get_mut(child) {
+                image.color.set_alpha(alpha);
+            }
+        }
+    }
+}
+
+fn tooltip_text(item_type: ItemType, with_recipe: bool) -> String {
+    let mut lines = vec![
+        format!("{} (stack of {})", item_type.name, item_type.stack_size),
+        item_type.description.to_string(),
+    ];
+    if let Some(recipe) = crafting::recipe_for(item_type).filter(|_| with_recipe) {
+        let ingredients: Vec<String> = recipe
+            .ingredients
+            .iter()
+            .map(|(t, count)| format!("{} x {}", count, t.name))
+            .collect();
+        lines.push(format!("Ingredients: {}", ingredients.join(", ")));
+        lines.push(format!("Time: {:.1}s", recipe.time_ms as f32 / 1000.0));
+    }
+    let used_in: Vec<&str> = crafting::recipes_using(item_type)
+        .iter()
+        .map(|r| r.result.name)
+        .collect();
+    if !used_in.is_empty() {
+        lines.push(format!("Used in: {}", used_in.join(", ")));
+    }
+    lines.join("\n")
+}
+
+/// Shows a tooltip next to the cursor for the hovered inventory slot or recipe.
+pub fn update_tooltip(
+    q_windows: Query<&Window, With<PrimaryWindow>>,
+    q_player: Query<&Player>,
+    q_slots: Query<(&InventoryItem, &Interaction)>,
+    q_craftables: Query<(&CraftableItem, &Interaction)>,
+    mut q_tooltip: Query<(&mut Style, &mut Visibility, &Children), With<Tooltip>>,
+    mut q_text: Query<&mut Text>,
+) {
+    let Ok((mut style, mut visibility, children)) = q_tooltip.get_single_mut() else {
+        return;
+    };
+    let player = q_player.single();
+    let hovered_slot = q_slots
+        .iter()
+        .filter(|(_, interaction)| **interaction != Interaction::None)
+        .find_map(|(slot, _)| player.inventory.get(slot.idx).copied().flatten())
+        .map(|(item_type, _)| (item_type, false));
+    let hovered_recipe = q_craftables
+        .iter()
+        .filter(|(_, interaction)| **interaction != Interaction::None)
+        .find_map(|(craftable, _)| craftable.item_type)
+        .map(|item_type| (item_type, true));
+    let cursor = q_windows.single().cursor_position();
+
+    let (Some((item_type, with_recipe)), Some(cursor)) = (hovered_slot.or(hovered_recipe), cursor)
+    else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    *visibility = Visibility::Visible;
+    style.left = Val::Px(cursor.x + 16.0);
+    style.top = Val::Px(cursor.y + 16.0);
+    if let Some(mut text) = children.first().and_then(|&c| q_text.get_mut(c).ok()) {
+        text.sections[0].value = tooltip_text(item_type, with_recipe);
+    }
+}
+
+/// Switches the recipe grid to the clicked category tab.
+pub fn update_crafting_tabs(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut selected: ResMut<SelectedCategory>,
+    mut q_tabs: Query<(&CategoryTab, &Interaction, &mut BackgroundColor)>,
+    q_grids: Query<(Entity, Ref<RecipeGrid>)>,
+) {
+    for (tab, interaction, mut bg) in q_tabs.iter_mut() {
+        if *interaction == Interaction::Pressed && selected.0 != tab.category {
+            selected.0 = tab.category;
+        }
+        bg.0 = if tab.category == selected.0 || *interaction == Interaction::Hovered {
+            COLOR_ITEM_BG_HOVER
+        } else {
+            COLOR_ITEM_BG_NORMAL
+        };
+    }
+
+    for (grid, grid_ref) in q_grids.iter() {
+        if selected.is_changed() || grid_ref.is_added() {
+            commands.entity(grid).despawn_descendants();
+            commands.entity(grid).with_children(|parent| {
+                spawn_recipe_grid(parent, &asset_server, selected.0);
+            });
+        }
+    }
+}
+
+pub fn create_player_inventory_ui(mut commands: Commands) -> Entity {
     commands
         .spawn(NodeBundle {
             style: Style {
@@ -117,7 +289,26 @@ pub fn create_player_inventory_ui(
                     ..default()
                 })
                 .with_children(|parent| {
-                    for y in 0..9 {
+                    parent
+                        .spawn((
+                            ButtonBundle {
+                                style: Style {
+                                    height: Val::Px(24.0),
+                                    margin: UiRect::bottom(Val::Px(2.)),
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::Center,
+                                    ..default()
+                                },
+                                background_color: BackgroundColor(COLOR_ITEM_BG_NORMAL),
+                                ..default()
+                            },
+                            SortButton,
+                        ))
+                        .with_children(|parent| {
+                            create_outlined_text(parent, "Sort".to_string());
+                        });
+
+                    for y in 0..INVENTORY_SLOTS.div_ceil(INVENTORY_COLUMNS) {
                         parent
                             .spawn(NodeBundle {
                                 style: Style {
@@ -130,80 +321,24 @@ pub fn create_player_inventory_ui(
                             .with_children(|parent| {
                                 // A `NodeBundle` is used to display the logo the image as an `ImageBundle` can't automatically
                                 // size itself with a child node present.
-                                for x in 0..10 {
-                                    let idx = y * 10 + x;
-                                    let inv = if idx < inventory.len() {
-                                        Some(inventory[idx])
-                                    } else {
-                                        None
-                                    };
-                                    parent
-                                        .spawn((
-                                            ButtonBundle {
-                                                style: Style {
-                                                    padding: UiRect::all(Val::Px(2.)),
-                                                    margin: UiRect::all(Val::Px(1.)),
-                                                    ..default()
-                                                },
-                                                background_color: BackgroundColor(
-                                                    COLOR_ITEM_BG_NORMAL,
-                                                ),
+                                for x in 0..INVENTORY_COLUMNS {
+                                    let idx = y * INVENTORY_COLUMNS + x;
+                                    if idx >= INVENTORY_SLOTS {
+                                        break;
+                                    }
+                                    // the slots are filled in `update_inventory_ui`
+                                    parent.spawn((
+                                        ButtonBundle {
+                                            style: Style {
+                                                padding: UiRect::all(Val::Px(2.)),
+                                                margin: UiRect::all(Val::Px(1.)),
                                                 ..default()
                                             },
-                                            InventoryItem { idx },
-                                        ))
-                                        .with_children(|parent| {
-                                            if let Some((item_type, count)) = inv {
-                                                parent
-                                                    .spawn((
-                                                        NodeBundle {
-                                                            style: Style {
-                                                                width: Val::Px(32.0),
-                                                                height: Val::Px(32.0),
-                                                                ..default()
-                                                            },
-                                                            ..default()
-                                                        },
-                                                        UiImage::new(asset_server.load(format!(
-                                                            "textures/items/{}.png",
-                                                            item_type.texture_name
-                                                        ))),
-                                                    ))
-                                                    .with_children(|parent| {
-                                                        parent
-                                                            .spawn(NodeBundle {
-                                                                style: Style {
-                                                                    width: Val::Percent(100.0),
-                                                                    height: Val::Percent(100.0),
-                                                                    justify_content:
-                                                                        JustifyContent::End,
-                                                                    align_items: AlignItems::End,
-                                                                    ..default()
-                                                                },
-                                                                ..default()
-                                                            })
-                                                            .with_children(|parent| {
-                                                                create_outlined_text(
-                                                                    parent,
-                                                                    format!("{}", count),
-                                                                );
-                                                            });
-                                                    });
-                                            } else {
-                                                parent
-                                                    .spawn((NodeBundle {
-                                                        style: Style {
-                                                            width: Val::Px(32.0),
-                                                            height: Val::Px(32.0),
-                                                            ..default()
-                                                        },
-                                                        ..default()
-                                                    },))
-                                                    .with_children(|parent| {
-                                                        parent.spawn((NodeBundle { ..default() },));
-                                                    });
-                                            }
-                                        });
+                                            background_color: BackgroundColor(COLOR_ITEM_BG_NORMAL),
+                                            ..default()
+                                        },
+                                        InventoryItem { idx },
+                                    ));
                                 }
                             });
                     }
@@ -238,33 +373,26 @@ pub fn create_player_inventory_ui(
                             ..default()
                         })
                         .with_children(|parent| {
-                            for x in 0..4 {
-                                let category_label = match x {
-                                    0 => "Logistics",
-                                    1 => "Production",
-                                    2 => "Intermediate",
-                                    3 => "Combat",
-                                    _ => unreachable!(),
-                                };
-                                let is_selected = x == 0; // TODO: use state for this
+                            for category in RecipeCategory::ALL {
+                                let category_label = category.label();
+                                // the selected tab is highlighted in `update_crafting_tabs`
                                 parent
-                                    .spawn((ButtonBundle {
-                                        style: Style {
-                                            height: Val::Px(32.0 + 4.0),
-                                            padding: UiRect::all(Val::Px(2.)),
-                                            margin: UiRect::all(Val::Px(1.)),
-                                            justify_content: JustifyContent::Center,
-                                            align_items: AlignItems::Center,
-                                            flex_grow: 1.0,
+                                    .spawn((
+                                        ButtonBundle {
+                                            style: Style {
+                                                height: Val::Px(32.0 + 4.0),
+                                                padding: UiRect::all(Val::Px(2.)),
+                                                margin: UiRect::all(Val::Px(1.)),
+                                                justify_content: JustifyContent::Center,
+                                                align_items: AlignItems::Center,
+                                                flex_grow: 1.0,
+                                                ..default()
+                                            },
+                                            background_color: BackgroundColor(COLOR_ITEM_BG_NORMAL),
                                             ..default()
                                         },
-                                        background_color: BackgroundColor(if is_selected {
-                                            COLOR_ITEM_BG_HOVER
-                                        } else {
-                                            COLOR_ITEM_BG_NORMAL
-                                        }),
-                                        ..default()
-                                    },))
+                                        CategoryTab { category },
+                                    ))
                                     .with_children(|parent| {
                                         parent
                                             .spawn((NodeBundle {
@@ -294,81 +422,171 @@ pub fn create_player_inventory_ui(
                             }
                         });
 
-                    for y in 0..8 {
-                        parent
-                            .spawn(NodeBundle {
+                    parent.spawn((
+                        NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Column,
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        RecipeGrid,
+                    ));
+                });
+
+            parent
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            padding: UiRect::all(Val::Px(6.0)),
+                            max_width: Val::Px(300.0),
+                            ..default()
+                        },
+                        background_color: BackgroundColor(COLOR_ITEM_BORDER),
+                        visibility: Visibility::Hidden,
+                        z_index: ZIndex::Global(1),
+                        ..default()
+                    },
+                    Tooltip,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "",
+                        TextStyle {
+                            color: Color::hsv(0.0, 0.0, 0.9),
+                            font_size: 14.0,
+                            ..default()
+                        },
+                    ));
+                });
+        })
+        .id()
+}
+
+fn spawn_inventory_slot(
+    parent: &mut ChildBuilder<'_>,
+    asset_server: &Res<AssetServer>,
+    inv: Option<(ItemType, usize)>,
+) {
+    if let Some((item_type, count)) = inv {
+        parent
+            .spawn((
+                NodeBundle {
+                    style: Style {
+                        width: Val::Px(32.0),
+                        height: Val::Px(32.0),
+                        ..default()
+                    },
+                    ..default()
+                },
+                UiImage::new(
+                    asset_server.load(format!("textures/items/{}.png", item_type.texture_name)),
+                ),
+            ))
+            .with_children(|parent| {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            width: Val::Percent(100.0),
+                            height: Val::Percent(100.0),
+                            justify_content: JustifyContent::End,
+                            align_items: AlignItems::End,
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        create_outlined_text(parent, format!("{}", count));
+                    });
+            });
+    } else {
+        parent
+            .spawn((NodeBundle {
+                style: Style {
+                    width: Val::Px(32.0),
+                    height: Val::Px(32.0),
+                    ..default()
+                },
+                ..default()
+            },))
+            .with_children(|parent| {
+                parent.spawn((NodeBundle { ..default() },));
+            });
+    }
+}
+
+/// Fills the recipe grid with the recipes of a category, in their order.
+fn spawn_recipe_grid(
+    parent: &mut ChildBuilder<'_>,
+    asset_server: &Res<AssetServer>,
+    category: RecipeCategory,
+) {
+    let recipes = crafting::recipes_in(category);
+    for y in 0..8 {
+        parent
+            .spawn(NodeBundle {
+                style: Style {
+                    align_items: AlignItems::FlexStart,
+                    ..default()
+                },
+                background_color: BackgroundColor(COLOR_ITEM_BG_NORMAL),
+                ..default()
+            })
+            .with_children(|parent| {
+                for x in 0..10 {
+                    let idx = y * 10 + x;
+                    let item_type = recipes.get(idx).map(|r| r.result);
+                    parent
+                        .spawn((
+                            ButtonBundle {
                                 style: Style {
-                                    align_items: AlignItems::FlexStart,
+                                    padding: UiRect::all(Val::Px(2.)),
+                                    margin: UiRect::all(Val::Px(1.)),
                                     ..default()
                                 },
                                 background_color: BackgroundColor(COLOR_ITEM_BG_NORMAL),
                                 ..default()
-                            })
-                            .with_children(|parent| {
-                                for x in 0..10 {
-                                    let idx = y * 10 + x;
-                                    let item_type = match (x, y) {
-                                        (0, 0) => Some(items::BELT),
-                                        (0, 1) => Some(items::INSERTER),
-                                        (0, 2) => Some(items::FURNACE),
-                                        (0, 3) => Some(items::MINER),
-                                        _ => None,
-                                    };
-                                    parent
-                                        .spawn((
-                                            ButtonBundle {
-                                                style: Style {
-                                                    padding: UiRect::all(Val::Px(2.)),
-                                                    margin: UiRect::all(Val::Px(1.)),
-                                                    ..default()
-                                                },
-                                                background_color: BackgroundColor(
-                                                    COLOR_ITEM_BG_NORMAL,
-                                                ),
-                                                ..default()
-                                            },
-                                            CraftableItem { item_type },
-                                        ))
-                                        .with_children(|parent| {
-                                            if let Some(item_type) = item_type {
-                                                parent.spawn((
-                                                    NodeBundle {
-                                                        style: Style {
-                                                            width: Val::Px(32.0),
-                                                            height: Val::Px(32.0),
-                                                            ..default()
-                                                        },
-                                                        ..default()
-                                                    },
-                                                    UiImage::new(asset_server.load(format!(
-                                                        "textures/items/{}.png",
-                                                        item_type.texture_name
-                                                    ))),
-                                                ));
-                                            } else {
-                                                parent
-                                                    .spawn((NodeBundle {
-                                                        style: Style {
-                                                            width: Val::Px(32.0),
-                                                            height: Val::Px(32.0),
-                                                            ..default()
-                                                        },
-                                                        ..default()
-                                                    },))
-                                                    .with_children(|parent| {
-                                                        parent.spawn((NodeBundle { ..default() },));
-                                                    });
-                                            }
-                                        });
-                                }
-                            });
-                    }
-                });
-        })
-        .id()
+                            },
+                            CraftableItem { item_type },
+                        ))
+                        .with_children(|parent| {
+                            if let Some(item_type) = item_type {
+                                parent.spawn((
+                                    NodeBundle {
+                                        style: Style {
+                                            width: Val::Px(32.0),
+                                            height: Val::Px(32.0),
+                                            ..default()
+                                        },
+                                        ..default()
+                                    },
+                                    UiImage::new(asset_server.load(format!(
+                                        "textures/items/{}.png",
+                                        item_type.texture_name
+                                    ))),
+                                ));
+                            } else {
+                                parent
+                                    .spawn((NodeBundle {
+                                        style: Style {
+                                            width: Val::Px(32.0),
+                                            height: Val::Px(32.0),
+                                            ..default()
+                                        },
+                                        ..default()
+                                    },))
+                                    .with_children(|parent| {
+                                        parent.spawn((NodeBundle { ..default() },));
+                                    });
+                            }
+                        });
+                }
+            });
+    }
 }
 
-fn create_outlined_text(parent: &mut ChildBuilder<'_>, text_str: String) {
+pub fn create_outlined_text(parent: &mut ChildBuilder<'_>, text_str: String) {
     parent
         .spawn(NodeBundle {
             style: Style { ..default() },
@@ -3,11 +3,16 @@ use std::f32::consts::PI;
 use bevy::{math::vec2, prelude::*, utils::HashMap, window::PrimaryWindow};
 
 use crate::{
-    calc_rotating_tile_transform, items, sprites, tiles, DroppedItem, GameWorld, InputState,
+    actions::PlayerReach, calc_rotating_tile_transform, cursor_cell, items, placement::Placement,
+    sprites, stats::ProductionStats, tiles, DroppedItem, GameWorld, InputState, ItemContainer,
     ItemMover, ItemProcessor, PlacedTile, PreviewTile, ResourceProducer, ResourceTile,
-    TileRotation,
+    TileRotation, TileType,
 };
 
+const PREVIEW_COLOR: Color = Color::srgba(1.0, 1.0, 1.0, 0.7);
+const INVALID_PREVIEW_COLOR: Color = Color::srgba(1.0, 0.3, 0.3, 0.7);
+const OUT_OF_REACH_PREVIEW_COLOR: Color = Color::srgba(0.5, 0.5, 0.5, 0.4);
+
 pub fn update_preview_tile(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
@@ -16,38 +21,28 @@ pub fn update_preview_tile(
     input_state: Res<InputState>,
     q_preview_tiles: Query<Entity, With<PreviewTile>>,
     game_world: Res<GameWorld>,
+    placement: Placement,
+    q_player: Query<(&Transform, &PlayerReach)>,
 ) {
     for e in q_preview_tiles.iter() {
         commands.entity(e).despawn();
     }
 
     if let Some(item) = input_state.item_in_hand {
-        let tile = if item == items::BELT {
-            Some(tiles::BELT)
-        } else if item == items::MINER {
-            Some(tiles::MINER)
-        } else if item == items::INSERTER {
-            Some(tiles::INSERTER)
-        } else if item == items::FURNACE {
-            Some(tiles::FURNACE)
-        } else {
-            None
-        };
-
-        if let Some(tile_type) = tile {
-            let window = q_windows.single();
+        if let Some(tile_type) = tiles::for_item(item) {
             let (camera, camera_transform) = q_camera.single();
-
-            let mouse_pos = window
-                .cursor_position()
-                .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor))
-                .map(|ray| ray.origin.truncate());
-
-            if let Some(pos) = mouse_pos {
-                let x = (pos.x / 32.0 + 0.5).floor() as i32;
-                let y = (pos.y / 32.0 + 0.5).floor() as i32;
-
+            if let Some((x, y)) = cursor_cell(q_windows.single(), camera, camera_transform) {
                 if !game_world.tiles.contains_key(&(x, y)) {
+                    let (player_transform, reach) = q_player.single();
+                    let distance = (vec2(x as f32, y as f32) * 32.0)
+                        .distance(player_transform.translation.truncate());
+                    let color = if distance > reach.build {
+                        OUT_OF_REACH_PREVIEW_COLOR
+                    } else if placement.is_valid(&game_world, tile_type, x, y) {
+                        PREVIEW_COLOR
+                    } else {
+                        INVALID_PREVIEW_COLOR
+                    };
                     commands.spawn((
                         sprites::create_preview_sprite(
                             &asset_server,
@@ -55,20 +50,25 @@ pub fn update_preview_tile(
                             x,
                             y,
                             input_state.rotation,
+                            color,
                         ),
                         PreviewTile,
                     ));
                     if tile_type.rotating_texture_name.is_some() {
                         let anchor = vec2(0.0, -0.5 + 3.0 / 32.0);
+                        let tile = PlacedTile {
+                            tile_type,
+                            rotation: input_state.rotation,
+                            x,
+                            y,
+                        };
                         commands.spawn((
                             sprites::create_rotating_preview_sprite(
                                 &asset_server,
-                                tile_type,
-                                x,
-                                y,
-                                input_state.rotation,
+                                &tile,
                                 anchor,
                                 PI * 0.5,
+                                color,
                             ),
                             PreviewTile,
                         ));
@@ -94,6 +94,15 @@ pub fn update_rotating_tiles(
 
 const MIN_ITEM_DIST: f32 = 14.0;
 
+/// Pixels per tick that items on a belt of this type move, if it is a belt.
+pub fn belt_speed(tile_type: TileType) -> Option<f32> {
+    match tile_type {
+        t if t == tiles::BELT => Some(1.0),
+        t if t == tiles::FAST_BELT => Some(2.0),
+        _ => None,
+    }
+}
+
 pub fn update_tiles(
     q_tiles: Query<&PlacedTile>,
     mut q_items: Query<(Entity, &mut Transform), With<DroppedItem>>,
@@ -102,7 +111,7 @@ pub fn update_tiles(
         let tx = tile.x as f32 * 32.0;
         let ty = tile.y as f32 * 32.0 - 16.0;
 
-        if tile.tile_type == tiles::BELT {
+        if let Some(speed) = belt_speed(tile.tile_type) {
             let mut item_movements = HashMap::new();
             for (entity, transform) in q_items.iter() {
                 let item_pos = &transform.translation;
@@ -119,7 +128,7 @@ pub fn update_tiles(
                         3 => vec2(0.0, -1.0),
                         _ => unreachable!(),
                     };
-                    let mut new_pos = transform.translation + dir.extend(0.0);
+                    let mut new_pos = transform.translation + (dir * speed).extend(0.0);
 
                     match tile.rotation {
                         0 | 2 => {
@@ -202,6 +211,7 @@ pub fn update_movers(
     mut q_movers: Query<(&mut ItemMover, &mut TileRotation, &Transform)>,
     q_items: Query<(Entity, &Transform, &DroppedItem)>,
     mut q_processors: Query<(&Transform, &mut ItemProcessor)>,
+    mut q_containers: Query<(&Transform, &mut ItemContainer)>,
 ) {
     for (mut mover, mut rot, tr) in q_movers.iter_mut() {
         match mover.item {
@@ -222,6 +232,20 @@ pub fn update_movers(
                             mover.item = None;
                             pr.item = Some(item);
                         }
+                    } else if let Some((_, mut container)) =
+                        q_containers.iter_mut().find(|(tr, _)| {
+                            tr.translation.truncate().distance_squared(pos.truncate()) < 16.0 * 16.0
+                        })
+                    {
+                        if container.accessible && container.insert(item) {
+                            let from = rot.to;
+                            let to = rot.from;
+                            rot.from = from;
+                            rot.to = to;
+                            rot.time = 0.0;
+
+                            mover.item = None;
+                        }
                     } else if !q_items
                         .iter()
                         .map(|(_, tr, _)| tr.translation)
@@ -289,6 +313,22 @@ pub fn update_movers(
                                 rot.to = to;
                                 rot.time = 0.0;
 
+                                mover.item = Some(item_type);
+                            }
+                        }
+                    } else if let Some((_, mut container)) =
+                        q_containers.iter_mut().find(|(tr, _)| {
+                            tr.translation.truncate().distance_squared(pos.truncate()) < 16.0 * 16.0
+                        })
+                    {
+                        if container.accessible && container.extractable {
+                            if let Some(item_type) = container.take_any() {
+                                let from = rot.to;
+                                let to = rot.from;
+                                rot.from = from;
+                                rot.to = to;
+                                rot.time = 0.0;
+
                                 mover.item = Some(item_type);
                             }
                         }
@@ -305,6 +345,7 @@ pub fn update_miners(
     mut q_tiles: Query<(&PlacedTile, &mut ResourceProducer)>,
     q_items: Query<&Transform, With<DroppedItem>>,
     q_resource_tiles: Query<&ResourceTile>,
+    mut stats: ResMut<ProductionStats>,
     time: Res<Time>,
 ) {
     for (tile, mut producer) in q_tiles.iter_mut() {
@@ -343,6 +384,7 @@ pub fn update_miners(
                             sprites::create_dropped_item_sprite(&asset_server, &item, pos.x, pos.y),
                             item,
                         ));
+                        stats.record_produced(item_type, 1);
                     }
                 }
             }
@@ -350,7 +392,11 @@ pub fn update_miners(
     }
 }
 
-pub fn update_item_processors(mut q_processors: Query<&mut ItemProcessor>, time: Res<Time>) {
+pub fn update_item_processors(
+    mut q_processors: Query<&mut ItemProcessor>,
+    mut stats: ResMut<ProductionStats>,
+    time: Res<Time>,
+) {
     for mut processor in q_processors.iter_mut() {
         if processor.timer.tick(time.delta()).finished() {
             if let Some(item_type) = processor.item {
@@ -372,9 +418,67 @@ pub fn update_item_processors(mut q_processors: Query<&mut ItemProcessor>, time:
                         processor.item = None;
                         processor.output = Some(new_output);
                         processor.timer.reset();
+                        stats.record_consumed(item_type, 1);
+                        stats.record_produced(output_item_type, 1);
                     }
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ItemType;
+
+    fn take_from(container: ItemContainer) -> (Option<ItemType>, usize) {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .add_systems(Update, update_movers);
+        let mover = app
+            .world_mut()
+            .spawn((
+                ItemMover { item: None },
+                TileRotation {
+                    anchor: Vec2::ZERO,
+                    speed: 1.0,
+                    from: -PI * 0.5,
+                    to: PI * 0.5,
+                    time: 1.0,
+                },
+                Transform::default(),
+            ))
+            .id();
+        // the pickup side of the inserter
+        let target = app
+            .world_mut()
+            .spawn((Transform::from_xyz(0.0, 16.0, 0.0), container))
+            .id();
+        app.update();
+
+        let item = app.world().get::<ItemMover>(mover).unwrap().item;
+        let left = app.world().get::<ItemContainer>(target).unwrap().total();
+        (item, left)
+    }
+
+    #[test]
+    fn inserter_takes_from_chest() {
+        let mut chest = ItemContainer::new(10);
+        chest.insert(items::AMMO);
+
+        assert_eq!(take_from(chest), (Some(items::AMMO), 0));
+    }
+
+    #[test]
+    fn inserter_never_takes_from_turret() {
+        let mut turret = ItemContainer {
+            filter: Some(items::AMMO),
+            extractable: false,
+            ..ItemContainer::new(10)
+        };
+        turret.insert(items::AMMO);
+
+        assert_eq!(take_from(turret), (None, 1));
+    }
+}
@@ -0,0 +1,100 @@
+use bevy::prelude::*;
+
+use crate::{
+    actions::{self, PlayerReach},
+    history::{BuildAction, BuildActionKind, BuildHistory},
+    selection::AreaSelected,
+    tiles, GameWorld, Health, InputState, PlacedTile, Player, SelectionTool, TileRotation,
+};
+
+type TilePartsQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut PlacedTile,
+        &'static mut Handle<Image>,
+        Option<&'static mut TileRotation>,
+        Option<&'static mut Health>,
+    ),
+>;
+
+/// Replaces tiles in selected areas with their upgraded tier. The existing entities are kept
+/// and only changed, so rotation and contents stay as they are.
+pub fn handle_upgrade_planner(
+    asset_server: Res<AssetServer>,
+    mut input_state: ResMut<InputState>,
+    mut area_selected: EventReader<AreaSelected>,
+    mut q_player: Query<(&mut Player, &Transform, &PlayerReach)>,
+    mut game_world: ResMut<GameWorld>,
+    mut history: ResMut<BuildHistory>,
+    mut q_tiles: TilePartsQuery,
+) {
+    if input_state.toggling_upgrade_tool {
+        if input_state.selection_tool == Some(SelectionTool::Upgrade) {
+            input_state.selection_tool = None;
+        } else {
+            input_state.selection_tool = Some(SelectionTool::Upgrade);
+            input_state.item_in_hand = None;
+        }
+    }
+
+    let (mut player, player_transform, reach) = q_player.single_mut();
+    let player_pos = player_transform.translation.truncate();
+    for area in area_selected.read() {
+        if area.tool != SelectionTool::Upgrade {
+            continue;
+        }
+        for tile in game_world.tiles.values_mut() {
+            // tiles out of reach are left as they are
+            let tile_pos = Vec2::new(tile.x as f32, tile.y as f32) * 32.0;
+            if !area.contains(tile.x, tile.y) || tile_pos.distance(player_pos) > reach.build {
+                continue;
+            }
+            let Some(upgrade) = tiles::upgrade_of(tile.tile_type) else {
+                continue;
+            };
+            if !player.decrement_inventory(upgrade.item_to_drop) {
+                continue;
+            }
+            if !player.increment_inventory(tile.tile_type.item_to_drop) {
+                // no room for the replaced tile, so the upgrade is put back and skipped
+                let _ = player.increment_inventory(upgrade.item_to_drop);
+                continue;
+            }
+            // undone like removing the old tile and placing the upgrade
+            history.record(BuildAction {
+                kind: BuildActionKind::Deleted,
+                tile: tile.clone(),
+                settings: None,
+            });
+            tile.tile_type = upgrade;
+            history.record(BuildAction {
+                kind: BuildActionKind::Placed,
+                tile: tile.clone(),
+                settings: None,
+            });
+
+            for (mut placed, mut texture, rotation, health) in q_tiles
+                .iter_mut()
+                .filter(|(t, ..)| t.x == tile.x && t.y == tile.y)
+            {
+                placed.tile_type = upgrade;
+                let texture_name = match rotation {
+                    Some(mut rotation) => {
+                        rotation.speed = actions::inserter_speed(upgrade);
+                        upgrade
+                            .rotating_texture_name
+                            .unwrap_or(upgrade.texture_name)
+                    }
+                    None => upgrade.texture_name,
+                };
+                *texture = asset_server.load(format!("textures/tiles/{}.png", texture_name));
+                if let Some(mut health) = health {
+                    let max = upgrade.max_health as f32;
+                    health.current *= max / health.max;
+                    health.max = max;
+                }
+            }
+        }
+    }
+}